@@ -0,0 +1,383 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::Value;
+
+use crate::config::include;
+use crate::config::model::{self, Model};
+
+// Everything --alias-check has to say about one spot in one file. The position
+// is where the key starts, 1-based like an editor counts; a problem with a
+// file as a whole has none.
+pub struct Problem {
+    // The place of the file in the order they are checked in, so that the
+    // problems come out file by file, and top to bottom within each.
+    order: usize,
+    file: PathBuf,
+    position: Option<(usize, usize)>,
+    message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(
+                f,
+                "{}:{}:{}: {}",
+                self.file.display(),
+                line,
+                column,
+                self.message
+            ),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+struct Checker {
+    problems: Vec<Problem>,
+    // Every file that parses, in the order the wrapper merges them, with its
+    // place in the order they are checked in: the model validates them
+    // together, since a conflict is between two of them.
+    layers: Vec<model::Layer>,
+    orders: Vec<usize>,
+    checked: Vec<PathBuf>,
+    // The files whose includes are being checked right now, the outermost
+    // first, each as it is known and as it was named.
+    chain: Vec<(PathBuf, PathBuf)>,
+}
+
+// The messages include.rs and the parsers produce are meant for a screen of
+// their own, and name the file themselves; here the file is named in front.
+fn bare<'a>(message: &'a str, file: &Path) -> &'a str {
+    let message = message.strip_prefix("[ERROR] ").unwrap_or(message);
+    let prefix = format!("{}: ", file.display());
+    message.strip_prefix(prefix.as_str()).unwrap_or(message)
+}
+
+impl Checker {
+    fn report(&mut self, order: usize, layer: &model::Layer, path: &[String], message: String) {
+        self.problems.push(Problem {
+            order,
+            file: layer.file().to_path_buf(),
+            position: layer.position(path),
+            message,
+        });
+    }
+
+    // In the order the wrapper merges them: what a file includes, the file
+    // itself, then what it includes on a condition, whether or not the
+    // condition holds here.
+    fn check_file(&mut self, file: &Path, project: bool) {
+        let identity = include::canonical(file);
+        if self.checked.contains(&identity) {
+            return;
+        }
+        self.checked.push(identity.clone());
+        let order = self.checked.len();
+
+        let text = match fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(e) => {
+                self.problems.push(Problem {
+                    order,
+                    file: file.to_path_buf(),
+                    position: None,
+                    message: format!("cannot be read: {}", e),
+                });
+                return;
+            }
+        };
+        let value = match text.parse::<Value>() {
+            Ok(value) => value,
+            Err(e) => {
+                self.problems.push(Problem {
+                    order,
+                    file: file.to_path_buf(),
+                    position: e.span().map(|span| model::line_column(&text, span.start)),
+                    message: e.message().trim().to_string(),
+                });
+                return;
+            }
+        };
+        let layer = model::Layer::new(file.to_path_buf(), text, value);
+
+        let (below, above) = match include::named_files(layer.value(), file) {
+            Ok(named) if !project => named,
+            Ok(_) => (vec![], vec![]),
+            Err(e) => {
+                let key = if layer.value().get("include_if").is_some() {
+                    "include_if"
+                } else {
+                    "include"
+                };
+                self.report(
+                    order,
+                    &layer,
+                    &[key.to_string()],
+                    bare(&e, file).to_string(),
+                );
+                (vec![], vec![])
+            }
+        };
+        if project {
+            for key in ["include", "include_if"] {
+                if layer.value().get(key).is_some() {
+                    let message = format!("'{}' is not read from a project file", key);
+                    self.report(order, &layer, &[key.to_string()], message);
+                }
+            }
+        }
+        let include_position = layer.position(&["include".to_string()]);
+        let include_if_position = layer.position(&["include_if".to_string()]);
+        self.chain.push((identity, file.to_path_buf()));
+        for included in below {
+            self.check_included(&included, order, file, include_position);
+        }
+        self.layers.push(layer);
+        self.orders.push(order);
+        for included in above {
+            self.check_included(&included, order, file, include_if_position);
+        }
+        self.chain.pop();
+    }
+
+    // A file that has been checked already is not checked again, but one that
+    // is still having its includes checked is a circle: the wrapper refuses to
+    // load it, so it is reported at the key of the file that closes it.
+    fn check_included(
+        &mut self,
+        included: &Path,
+        order: usize,
+        file: &Path,
+        position: Option<(usize, usize)>,
+    ) {
+        let identity = include::canonical(included);
+        let Some(start) = self.chain.iter().position(|(seen, _)| seen == &identity) else {
+            self.check_file(included, false);
+            return;
+        };
+        let cycle: Vec<String> = self.chain[start..]
+            .iter()
+            .map(|(_, named)| named.display().to_string())
+            .chain(std::iter::once(included.display().to_string()))
+            .collect();
+        self.problems.push(Problem {
+            order,
+            file: file.to_path_buf(),
+            position,
+            message: format!("include cycle: {}", cycle.join(" -> ")),
+        });
+    }
+}
+
+// A config file that is not there is not a problem, except for the one that
+// ALIAS_CONFIG names.
+pub struct Layer {
+    pub file: PathBuf,
+    pub required: bool,
+    pub project: bool,
+}
+
+pub fn check(layers: &[Layer]) -> (usize, Vec<Problem>) {
+    let mut checker = Checker {
+        problems: vec![],
+        layers: vec![],
+        orders: vec![],
+        checked: vec![],
+        chain: vec![],
+    };
+    for layer in layers {
+        if layer.file.exists() {
+            checker.check_file(&layer.file, layer.project);
+        } else if layer.required {
+            checker.problems.push(Problem {
+                order: checker.checked.len(),
+                file: layer.file.clone(),
+                position: None,
+                message: "does not exist".to_string(),
+            });
+        }
+    }
+    let checked = checker.checked.len();
+    let mut problems = checker.problems;
+    let (model, found) = Model::load(checker.layers);
+    problems.extend(found.into_iter().map(|problem| {
+        let layer = &model.layers[problem.origin.layer];
+        Problem {
+            order: checker.orders[problem.origin.layer],
+            file: layer.file().to_path_buf(),
+            position: layer.position(&problem.origin.path),
+            message: problem.message,
+        }
+    }));
+    problems.sort_by_key(|problem| (problem.order, problem.position));
+    (checked, problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_files(files: &[(&str, &str)]) -> Vec<String> {
+        let directory = tempfile::tempdir().unwrap();
+        let layers: Vec<Layer> = files
+            .iter()
+            .map(|(name, contents)| {
+                let file = directory.path().join(name);
+                fs::write(&file, contents).unwrap();
+                Layer {
+                    file,
+                    required: false,
+                    project: false,
+                }
+            })
+            .collect();
+        let (_, problems) = check(&layers);
+        let prefix = format!(
+            "{}{}",
+            directory.path().display(),
+            std::path::MAIN_SEPARATOR
+        );
+        problems
+            .iter()
+            .map(|problem| problem.to_string().replace(&prefix, ""))
+            .collect()
+    }
+
+    fn check_one(contents: &str) -> Vec<String> {
+        check_files(&[("config.toml", contents)])
+    }
+
+    #[test]
+    fn a_clean_config_has_no_problems() {
+        let problems = check_one(
+            "executable = \"/usr/bin/git\"\n\
+             [alias]\n\
+             co = \"checkout\"\n\
+             ci = { args = [\"commit\", \"-m\", \"$1\"], description = \"commit\" }\n\
+             [alias.stash]\n\
+             _description = \"stash shortcuts\"\n\
+             ls = \"stash list\"\n",
+        );
+
+        assert!(problems.is_empty(), "unexpected problems: {:?}", problems);
+    }
+
+    #[test]
+    fn every_problem_is_reported_with_its_line_and_column() {
+        let problems = check_one(
+            "executable = 42\n\
+             colour = true\n\
+             [alias]\n\
+             ci = 'commit -m \"unclosed'\n\
+             n = 3\n",
+        );
+
+        assert_eq!(
+            vec![
+                "config.toml:1:1: 'executable' has to be a string, not an integer",
+                "config.toml:2:1: unknown key 'colour'",
+                "config.toml:4:1: alias 'ci': unclosed quote",
+                "config.toml:5:1: alias 'n' has to be a string, an array of steps or a table, not an integer",
+            ],
+            problems
+        );
+    }
+
+    #[test]
+    fn a_file_that_does_not_parse_is_reported_where_it_stops() {
+        let problems = check_one("[alias]\nco = \"checkout\n");
+
+        assert_eq!(1, problems.len(), "{:?}", problems);
+        assert!(
+            problems[0].starts_with("config.toml:2:"),
+            "unexpected problem: {}",
+            problems[0]
+        );
+    }
+
+    #[test]
+    fn problems_inside_groups_and_alias_tables_are_found() {
+        let problems = check_one(
+            "[alias.docker]\n\
+             _colour = \"blue\"\n\
+             ps = { args = \"ps -a\", tags = 3, colour = \"red\" }\n\
+             \"\" = \"images\"\n",
+        );
+
+        assert_eq!(
+            vec![
+                "config.toml:2:1: unknown group setting '_colour' (names starting with '_' are kept for _cwd, _default, _description, _env, _env_file, _env_from_command, _example, _prefix, _tags)",
+                "config.toml:3:24: 'tags' has to be a string or an array of strings, not an integer",
+                "config.toml:3:34: alias 'docker ps': unknown key 'colour'",
+                "config.toml:4:1: an empty name cannot be called",
+            ],
+            problems
+        );
+    }
+
+    #[test]
+    fn an_alias_and_a_group_of_the_same_name_in_two_layers_are_reported() {
+        let problems = check_files(&[
+            ("config.toml", "[alias.docker]\nps = \"ps\"\n"),
+            ("override.toml", "[alias]\ndocker = \"compose\"\n"),
+        ]);
+
+        assert_eq!(
+            vec![
+                "override.toml:2:1: 'docker' is an alias here and a group in config.toml:1:8: only one of the two is kept"
+            ],
+            problems
+        );
+    }
+
+    #[test]
+    fn included_files_are_checked_as_well() {
+        let problems = check_files(&[
+            ("shared.toml", "[alias]\nn = 3\n"),
+            ("config.toml", "include = \"shared.toml\"\n"),
+        ]);
+
+        assert_eq!(
+            vec![
+                "shared.toml:2:1: alias 'n' has to be a string, an array of steps or a table, not an integer"
+            ],
+            problems
+        );
+    }
+
+    #[test]
+    fn an_include_cycle_is_reported_at_the_key_that_closes_it() {
+        let problems = check_files(&[
+            ("config.toml", "include = \"a.toml\"\n"),
+            (
+                "a.toml",
+                "include = \"b.toml\"\n[alias]\nco = \"checkout\"\n",
+            ),
+            (
+                "b.toml",
+                "[[include_if]]\nenv = \"PATH\"\npath = \"c.toml\"\n",
+            ),
+            ("c.toml", "include = [\"a.toml\"]\n"),
+        ]);
+
+        assert_eq!(
+            vec!["c.toml:1:1: include cycle: a.toml -> b.toml -> c.toml -> a.toml"],
+            problems
+        );
+    }
+
+    #[test]
+    fn a_missing_include_is_reported_at_the_key() {
+        let problems = check_one("include = \"missing.toml\"\n");
+
+        assert_eq!(1, problems.len(), "{:?}", problems);
+        assert!(
+            problems[0].starts_with("config.toml:1:1: the included file"),
+            "unexpected problem: {}",
+            problems[0]
+        );
+    }
+}
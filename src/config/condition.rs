@@ -0,0 +1,405 @@
+use std::cell::OnceCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::Value;
+use toml::map::Map;
+
+use crate::config::glob;
+use crate::config::include::expand_path;
+
+// Where the wrapper has been started from. The repository is only looked for
+// when a condition asks about it: most configurations never do, and walking
+// up the directory tree on every call for nothing is not free.
+pub struct Location {
+    cwd: Option<PathBuf>,
+    git_dir: OnceCell<Option<PathBuf>>,
+    remote_urls: OnceCell<Vec<String>>,
+}
+
+impl Location {
+    pub fn current() -> Self {
+        Self::at(env::current_dir().ok())
+    }
+
+    pub fn at(cwd: Option<PathBuf>) -> Self {
+        Location {
+            cwd,
+            git_dir: OnceCell::new(),
+            remote_urls: OnceCell::new(),
+        }
+    }
+
+    fn git_dir(&self) -> Option<&Path> {
+        self.git_dir
+            .get_or_init(|| self.cwd.as_deref().and_then(find_git_dir))
+            .as_deref()
+    }
+
+    fn remote_urls(&self) -> &[String] {
+        self.remote_urls
+            .get_or_init(|| self.git_dir().map(remote_urls).unwrap_or_default())
+    }
+}
+
+// A worktree or a submodule has a .git file pointing at the real directory,
+// and the real directory is what a gitdir pattern is written against.
+fn find_git_dir(start: &Path) -> Option<PathBuf> {
+    let dot_git = start
+        .ancestors()
+        .map(|directory| directory.join(".git"))
+        .find(|candidate| candidate.exists())?;
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let contents = fs::read_to_string(&dot_git).ok()?;
+    let target = contents.trim().strip_prefix("gitdir:")?.trim();
+    let directory = dot_git.parent()?;
+    Some(directory.join(target))
+}
+
+// A linked worktree has no config of its own: it shares that of the main
+// repository, which its 'commondir' file points at.
+fn remote_urls(git_dir: &Path) -> Vec<String> {
+    let common = fs::read_to_string(git_dir.join("commondir"))
+        .map(|target| git_dir.join(target.trim()))
+        .unwrap_or_else(|_| git_dir.to_path_buf());
+    fs::read_to_string(common.join("config"))
+        .map(|config| remote_urls_in(&config))
+        .unwrap_or_default()
+}
+
+// Only as much of the git config format as it takes to find remote.*.url:
+// section and key names are case-insensitive, a section is spelled either
+// [remote "origin"] or [remote.origin], and a value may be quoted.
+fn remote_urls_in(config: &str) -> Vec<String> {
+    let mut in_remote = false;
+    let mut urls = vec![];
+    for line in config.lines().map(str::trim) {
+        if let Some(section) = line.strip_prefix('[') {
+            in_remote = section
+                .split(|c: char| c.is_whitespace() || c == '.' || c == ']')
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case("remote"));
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, value)) if in_remote && key.trim().eq_ignore_ascii_case("url") => {
+                urls.push(value.trim().trim_matches('"').to_string())
+            }
+            _ => {}
+        }
+    }
+    urls
+}
+
+// Patterns are matched against paths spelled with '/' whatever the platform.
+fn slashed(path: &Path) -> String {
+    let text = path.to_string_lossy();
+    if cfg!(windows) {
+        text.replace('\\', "/")
+    } else {
+        text.into_owned()
+    }
+}
+
+// The rules git applies to includeIf "gitdir:", so that a pattern copied from
+// a .gitconfig means the same thing here: a trailing '/' stands for everything
+// below it, a pattern that is not anchored anywhere can match at any depth,
+// and './' is the directory of the file the pattern is written in.
+fn path_pattern(pattern: &str, file: &Path) -> String {
+    let pattern = match (pattern.strip_prefix("./"), file.parent()) {
+        (Some(rest), Some(directory)) => directory.join(rest),
+        _ => expand_path(pattern),
+    };
+    let mut pattern = slashed(&pattern);
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+    if Path::new(&pattern).is_relative() && !pattern.starts_with('/') {
+        pattern.insert_str(0, "**/");
+    }
+    pattern
+}
+
+// A directory is inside itself: '~/work/**' holds in ~/work as well.
+fn path_matches(pattern: &str, path: &Path) -> bool {
+    let path = slashed(path);
+    glob::matches(pattern, &path) || glob::matches(pattern, &format!("{}/", path))
+}
+
+pub struct Condition {
+    cwd: Option<String>,
+    gitdir: Option<String>,
+    env: Option<(String, Option<String>)>,
+    remote: Option<String>,
+}
+
+const KEYS: [&str; 4] = ["cwd", "gitdir", "env", "remote"];
+
+// What an entry holds besides its predicates; include.rs reads those.
+const INCLUDE_KEYS: [&str; 2] = ["path", "optional"];
+
+impl Condition {
+    // Every predicate that is given has to hold.
+    pub fn holds(&self, location: &Location) -> bool {
+        let cwd = self.cwd.as_ref().is_none_or(|pattern| {
+            location
+                .cwd
+                .as_deref()
+                .is_some_and(|cwd| path_matches(pattern, cwd))
+        });
+        let gitdir = self.gitdir.as_ref().is_none_or(|pattern| {
+            location
+                .git_dir()
+                .is_some_and(|git_dir| path_matches(pattern, git_dir))
+        });
+        let env = self.env.as_ref().is_none_or(|(name, pattern)| {
+            match (
+                env::var(name).ok().filter(|value| !value.is_empty()),
+                pattern,
+            ) {
+                (Some(value), Some(pattern)) => glob::matches(pattern, &value),
+                (Some(_), None) => true,
+                (None, _) => false,
+            }
+        });
+        // Any remote will do: a fork has the client's repository as
+        // 'upstream' and the developer's own as 'origin'.
+        let remote = self.remote.as_ref().is_none_or(|pattern| {
+            location
+                .remote_urls()
+                .iter()
+                .any(|url| glob::matches(pattern, url))
+        });
+        cwd && gitdir && env && remote
+    }
+}
+
+fn string(table: &Map<String, Value>, key: &str, file: &Path) -> Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(text)) => Ok(Some(text.clone())),
+        Some(_) => Err(format!(
+            "[ERROR] {}: '{}' in 'include_if' has to be a string",
+            file.display(),
+            key
+        )),
+    }
+}
+
+pub fn parse(table: &Map<String, Value>, file: &Path) -> Result<Condition, String> {
+    // A misspelled predicate next to one that is spelled right would make the
+    // entry apply in more places than it was written for, without a word.
+    let known = || KEYS.iter().chain(&INCLUDE_KEYS);
+    if let Some(key) = table.keys().find(|key| !known().any(|known| known == key)) {
+        return Err(format!(
+            "[ERROR] {}: unknown key '{}' in 'include_if' (it can be {})",
+            file.display(),
+            key,
+            known()
+                .map(|key| format!("'{}'", key))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    let condition = Condition {
+        cwd: string(table, "cwd", file)?.map(|pattern| path_pattern(&pattern, file)),
+        gitdir: string(table, "gitdir", file)?.map(|pattern| path_pattern(&pattern, file)),
+        env: string(table, "env", file)?.map(|env| match env.split_once('=') {
+            Some((name, pattern)) => (name.to_string(), Some(pattern.to_string())),
+            None => (env, None),
+        }),
+        remote: string(table, "remote", file)?,
+    };
+    // Without a predicate the entry would apply everywhere, which is what
+    // 'include' is for; more likely a predicate has been misspelled.
+    if condition.cwd.is_none()
+        && condition.gitdir.is_none()
+        && condition.env.is_none()
+        && condition.remote.is_none()
+    {
+        return Err(format!(
+            "[ERROR] {}: an 'include_if' entry needs at least one of {}",
+            file.display(),
+            KEYS.map(|key| format!("'{}'", key)).join(", ")
+        ));
+    }
+    Ok(condition)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(toml: &str) -> Condition {
+        let value: Value = toml.parse().unwrap();
+        parse(
+            value.as_table().unwrap(),
+            Path::new("/etc/alias/config.toml"),
+        )
+        .unwrap()
+    }
+
+    fn location(cwd: &str) -> Location {
+        Location::at(Some(PathBuf::from(cwd)))
+    }
+
+    #[test]
+    fn a_cwd_pattern_holds_below_a_directory_and_in_it() {
+        let work = condition("cwd = \"/home/bob/work/**\"");
+
+        assert!(work.holds(&location("/home/bob/work/client/app")));
+        assert!(work.holds(&location("/home/bob/work")));
+        assert!(!work.holds(&location("/home/bob/personal")));
+    }
+
+    #[test]
+    fn a_trailing_slash_stands_for_everything_below_it() {
+        let work = condition("cwd = \"/home/bob/work/\"");
+
+        assert!(work.holds(&location("/home/bob/work/client")));
+        assert!(!work.holds(&location("/home/bob/workshop")));
+    }
+
+    #[test]
+    fn a_relative_pattern_matches_at_any_depth() {
+        let client = condition("cwd = \"client-*/\"");
+
+        assert!(client.holds(&location("/home/bob/work/client-acme/app")));
+        assert!(!client.holds(&location("/home/bob/work/personal")));
+    }
+
+    #[test]
+    fn without_a_current_directory_a_cwd_pattern_does_not_hold() {
+        assert!(!condition("cwd = \"/**\"").holds(&Location::at(None)));
+    }
+
+    #[test]
+    fn a_gitdir_pattern_is_matched_against_the_repository_of_the_current_directory() {
+        let directory = tempfile::tempdir().unwrap();
+        let repository = directory.path().join("work").join("app");
+        fs::create_dir_all(repository.join(".git")).unwrap();
+        fs::create_dir_all(repository.join("src")).unwrap();
+        let pattern = format!("gitdir = \"{}/\"", slashed(&directory.path().join("work")));
+
+        assert!(condition(&pattern).holds(&Location::at(Some(repository.join("src")))));
+        assert!(!condition(&pattern).holds(&Location::at(Some(directory.path().to_path_buf()))));
+    }
+
+    #[test]
+    fn a_git_file_is_followed_to_the_directory_it_points_at() {
+        let directory = tempfile::tempdir().unwrap();
+        let worktree = directory.path().join("worktree");
+        fs::create_dir_all(&worktree).unwrap();
+        fs::write(worktree.join(".git"), "gitdir: ../main/.git/worktrees/wt\n").unwrap();
+
+        assert_eq!(
+            Some(worktree.join("../main/.git/worktrees/wt")),
+            find_git_dir(&worktree)
+        );
+    }
+
+    #[test]
+    fn a_remote_pattern_is_matched_against_every_remote_of_the_repository() {
+        let directory = tempfile::tempdir().unwrap();
+        let repository = directory.path().join("app");
+        fs::create_dir_all(repository.join(".git")).unwrap();
+        fs::write(
+            repository.join(".git").join("config"),
+            "[core]\n\tbare = false\n\
+             [remote \"origin\"]\n\turl = git@github.com:bob/app.git\n\
+             [Remote \"upstream\"]\n\tURL = \"git@github.com:acme/app.git\"\n",
+        )
+        .unwrap();
+        let inside = Location::at(Some(repository.clone()));
+
+        assert!(condition("remote = \"*:acme/*\"").holds(&inside));
+        assert!(condition("remote = \"git@github.com:bob/*\"").holds(&inside));
+        assert!(!condition("remote = \"*:globex/*\"").holds(&inside));
+        assert!(
+            !condition("remote = \"**\"")
+                .holds(&Location::at(Some(directory.path().to_path_buf())))
+        );
+    }
+
+    #[test]
+    fn a_linked_worktree_has_the_remotes_of_its_main_repository() {
+        let directory = tempfile::tempdir().unwrap();
+        let main = directory.path().join("main").join(".git");
+        let linked = main.join("worktrees").join("wt");
+        fs::create_dir_all(&linked).unwrap();
+        fs::write(linked.join("commondir"), "../..\n").unwrap();
+        fs::write(
+            main.join("config"),
+            "[remote.origin]\nurl = https://example.com/acme/app\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec!["https://example.com/acme/app".to_string()],
+            remote_urls(&linked)
+        );
+    }
+
+    #[test]
+    fn an_env_predicate_asks_for_a_variable_or_for_its_value() {
+        unsafe {
+            env::set_var("CONDITION_CLIENT", "acme-prod");
+            env::set_var("CONDITION_EMPTY", "");
+        }
+        let anywhere = location("/");
+
+        assert!(condition("env = \"CONDITION_CLIENT\"").holds(&anywhere));
+        assert!(condition("env = \"CONDITION_CLIENT=acme-*\"").holds(&anywhere));
+        assert!(!condition("env = \"CONDITION_CLIENT=globex-*\"").holds(&anywhere));
+        assert!(!condition("env = \"CONDITION_EMPTY\"").holds(&anywhere));
+        assert!(!condition("env = \"CONDITION_NOT_SET\"").holds(&anywhere));
+    }
+
+    #[test]
+    fn every_predicate_given_has_to_hold() {
+        unsafe {
+            env::set_var("CONDITION_BOTH", "1");
+        }
+        let both = condition("cwd = \"/work/**\"\nenv = \"CONDITION_BOTH\"");
+        let neither_set = condition("cwd = \"/work/**\"\nenv = \"CONDITION_BOTH_NOT_SET\"");
+
+        assert!(both.holds(&location("/work/app")));
+        assert!(!both.holds(&location("/home")));
+        assert!(!neither_set.holds(&location("/work/app")));
+    }
+
+    #[test]
+    fn an_entry_without_a_predicate_is_rejected() {
+        let value: Value = "path = \"work.toml\"\noptional = true".parse().unwrap();
+
+        let error = parse(value.as_table().unwrap(), Path::new("config.toml"))
+            .err()
+            .expect("no predicate is given");
+
+        assert!(
+            error.contains("'cwd', 'gitdir', 'env', 'remote'"),
+            "unexpected error: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn a_misspelled_key_next_to_a_predicate_is_rejected() {
+        let value: Value = "path = \"work.toml\"\ncwd = \"/work/**\"\nenvv = \"PROD\""
+            .parse()
+            .unwrap();
+
+        let error = parse(value.as_table().unwrap(), Path::new("config.toml"))
+            .err()
+            .expect("'envv' is no predicate");
+
+        assert_eq!(
+            "[ERROR] config.toml: unknown key 'envv' in 'include_if' \
+             (it can be 'cwd', 'gitdir', 'env', 'remote', 'path', 'optional')",
+            error
+        );
+    }
+}
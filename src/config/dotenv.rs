@@ -0,0 +1,149 @@
+// The .env format as most tools read it: NAME=value a line, an optional
+// 'export' in front, comments and blank lines skipped. Single quotes keep a
+// value as it is, double quotes take \n, \t, \r, \" and \\ as escapes, and an
+// unquoted value ends at a ' #' comment. Nothing is expanded: a file shared
+// with other tools means the same thing to all of them.
+//
+// A line that does not parse is reported by its number alone. The file is
+// where secrets are kept, and the line could well be one.
+pub fn parse(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut variables = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((name, value)) = line.split_once('=') else {
+            return Err(format!("line {} is not NAME=value", number));
+        };
+        let name = name.trim();
+        if !is_name(name) {
+            return Err(format!(
+                "line {} does not start with a variable name",
+                number
+            ));
+        }
+        let value =
+            parse_value(value.trim()).map_err(|problem| format!("line {} {}", number, problem))?;
+        variables.push((name.to_string(), value));
+    }
+    Ok(variables)
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+const NOT_CLOSED: &str = "has a quote that is not closed";
+
+fn parse_value(value: &str) -> Result<String, &'static str> {
+    if let Some(rest) = value.strip_prefix('\'') {
+        let (quoted, after) = rest.split_once('\'').ok_or(NOT_CLOSED)?;
+        return after_quote(after).map(|_| quoted.to_string());
+    }
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut result = String::new();
+        let mut characters = rest.char_indices();
+        while let Some((index, character)) = characters.next() {
+            match character {
+                '"' => return after_quote(&rest[index + 1..]).map(|_| result),
+                '\\' => match characters.next().ok_or(NOT_CLOSED)?.1 {
+                    'n' => result.push('\n'),
+                    't' => result.push('\t'),
+                    'r' => result.push('\r'),
+                    other => {
+                        if other != '"' && other != '\\' {
+                            result.push('\\');
+                        }
+                        result.push(other)
+                    }
+                },
+                character => result.push(character),
+            }
+        }
+        return Err(NOT_CLOSED);
+    }
+    let value = match value.find(" #") {
+        Some(comment) => &value[..comment],
+        None => value,
+    };
+    Ok(value.trim_end().to_string())
+}
+
+fn after_quote(after: &str) -> Result<(), &'static str> {
+    let after = after.trim();
+    if after.is_empty() || after.starts_with('#') {
+        Ok(())
+    } else {
+        Err("has more after its closing quote")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(text: &str) -> Vec<(String, String)> {
+        parse(text).expect("a valid file")
+    }
+
+    fn pair(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn names_and_values_are_read_a_line_at_a_time() {
+        assert_eq!(
+            vec![pair("HOST", "staging"), pair("PORT", "8080")],
+            pairs("# staging\n\nHOST=staging\nexport PORT = 8080 # the default\n")
+        );
+    }
+
+    #[test]
+    fn single_quotes_keep_a_value_as_it_is() {
+        assert_eq!(
+            vec![pair("A", "two  words # and \\n")],
+            pairs("A='two  words # and \\n'")
+        );
+    }
+
+    #[test]
+    fn double_quotes_take_escapes() {
+        assert_eq!(
+            vec![pair("A", "line\none \"quoted\" \\ \\x")],
+            pairs("A=\"line\\none \\\"quoted\\\" \\\\ \\x\" # comment")
+        );
+    }
+
+    #[test]
+    fn an_empty_value_is_a_value() {
+        assert_eq!(vec![pair("A", ""), pair("B", "")], pairs("A=\nB=''"));
+    }
+
+    // The line itself is left out: it may hold the secret the file is for.
+    #[test]
+    fn a_line_that_does_not_parse_is_reported_by_its_number_only() {
+        assert_eq!(
+            Err("line 2 is not NAME=value".to_string()),
+            parse("A=1\nsecret-token\n")
+        );
+        assert_eq!(
+            Err("line 1 does not start with a variable name".to_string()),
+            parse("1A=secret")
+        );
+        assert_eq!(
+            Err("line 1 has a quote that is not closed".to_string()),
+            parse("A=\"secret")
+        );
+        assert_eq!(
+            Err("line 1 has more after its closing quote".to_string()),
+            parse("A='secret' trailing")
+        );
+    }
+}
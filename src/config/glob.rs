@@ -0,0 +1,163 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// Just enough of a glob for paths in a config file: '*' and '?' within one
+// path component, and '**' standing for any number of directories. Character
+// classes and braces are left out; nobody spells an include that way, and a
+// bracket is a legal character in a file name.
+pub fn has_wildcards(text: &str) -> bool {
+    text.contains('*') || text.contains('?')
+}
+
+fn matches_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) if rest.first() == Some(&'*') => {
+            let rest = &rest[1..];
+            // '**/' also matches no directory at all.
+            let rest_after_slash = rest.strip_prefix(&['/']).unwrap_or(rest);
+            (0..=text.len()).any(|skip| {
+                matches_from(rest, &text[skip..]) || matches_from(rest_after_slash, &text[skip..])
+            })
+        }
+        Some(('*', rest)) => (0..=text.len())
+            .take_while(|&skip| skip == 0 || text[skip - 1] != '/')
+            .any(|skip| matches_from(rest, &text[skip..])),
+        Some(('?', rest)) => match text.split_first() {
+            Some((c, text)) if *c != '/' => matches_from(rest, text),
+            _ => false,
+        },
+        Some((expected, rest)) => match text.split_first() {
+            Some((c, text)) if c == expected => matches_from(rest, text),
+            _ => false,
+        },
+    }
+}
+
+// A whole path against a whole pattern, both spelled with '/': a single '*'
+// stops at a separator, '**' does not.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches_from(&pattern, &text)
+}
+
+fn sorted_entries(directory: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(directory)
+        .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+        .unwrap_or_default();
+    entries.sort();
+    entries
+}
+
+fn expand_from(base: PathBuf, components: &[Component], found: &mut Vec<PathBuf>) {
+    let Some((component, rest)) = components.split_first() else {
+        if base.is_file() {
+            found.push(base);
+        }
+        return;
+    };
+    let name = component.as_os_str().to_string_lossy();
+    if name == "**" {
+        expand_from(base.clone(), rest, found);
+        for entry in sorted_entries(&base) {
+            if entry.is_dir() {
+                expand_from(entry, components, found);
+            }
+        }
+    } else if has_wildcards(&name) {
+        for entry in sorted_entries(&base) {
+            let entry_name = entry
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            // A hidden file is only matched by a pattern that asks for it,
+            // the way a shell does it: an editor's .swp is not an include.
+            if entry_name.starts_with('.') && !name.starts_with('.') {
+                continue;
+            }
+            if matches(&name, &entry_name) {
+                expand_from(entry, rest, found);
+            }
+        }
+    } else {
+        expand_from(base.join(component.as_os_str()), rest, found);
+    }
+}
+
+// The files a pattern matches, in the order of their paths, so that the order
+// they are merged in does not depend on the order a directory happens to list
+// them in.
+pub fn expand(pattern: &Path) -> Vec<PathBuf> {
+    let components: Vec<Component> = pattern.components().collect();
+    let mut found = Vec::new();
+    expand_from(PathBuf::new(), &components, &mut found);
+    found.dedup();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_star_matches_within_one_component() {
+        assert!(matches("*.toml", "docker.toml"));
+        assert!(matches("/home/*/work", "/home/bob/work"));
+        assert!(!matches("/home/*/work", "/home/bob/x/work"));
+        assert!(!matches("*.toml", "docker.yaml"));
+    }
+
+    #[test]
+    fn a_question_mark_matches_one_character() {
+        assert!(matches("k?s.toml", "k8s.toml"));
+        assert!(!matches("k?s.toml", "k88s.toml"));
+        assert!(!matches("a?b", "a/b"));
+    }
+
+    #[test]
+    fn a_double_star_matches_any_number_of_directories() {
+        assert!(matches("/home/bob/work/**", "/home/bob/work/client/app"));
+        assert!(matches("/home/bob/work/**", "/home/bob/work/"));
+        assert!(matches("**/compose.yaml", "compose.yaml"));
+        assert!(matches("/src/**/mod.rs", "/src/mod.rs"));
+        assert!(matches("/src/**/mod.rs", "/src/config/nested/mod.rs"));
+        assert!(!matches("/home/bob/work/**", "/home/bob/personal/app"));
+    }
+
+    #[test]
+    fn text_without_wildcards_has_to_match_exactly() {
+        assert!(matches("/etc/alias", "/etc/alias"));
+        assert!(!matches("/etc/alias", "/etc/alias2"));
+    }
+
+    #[test]
+    fn the_files_a_pattern_matches_are_listed_in_path_order() {
+        let directory = tempfile::tempdir().unwrap();
+        let shared = directory.path().join("shared");
+        fs::create_dir_all(shared.join("nested")).unwrap();
+        for name in ["b.toml", "a.toml", "notes.txt", ".hidden.toml"] {
+            fs::write(shared.join(name), "").unwrap();
+        }
+        fs::write(shared.join("nested").join("c.toml"), "").unwrap();
+
+        assert_eq!(
+            vec![shared.join("a.toml"), shared.join("b.toml")],
+            expand(&shared.join("*.toml"))
+        );
+        assert_eq!(
+            vec![
+                shared.join("a.toml"),
+                shared.join("b.toml"),
+                shared.join("nested").join("c.toml")
+            ],
+            expand(&shared.join("**").join("*.toml"))
+        );
+    }
+
+    #[test]
+    fn a_pattern_matching_nothing_lists_nothing() {
+        let directory = tempfile::tempdir().unwrap();
+        assert!(expand(&directory.path().join("missing").join("*.toml")).is_empty());
+    }
+}
@@ -0,0 +1,466 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::Value;
+
+use crate::config::condition::{self, Condition, Location};
+use crate::config::model::Layer;
+use crate::config::{glob, parse_value, read_text};
+use crate::environment::expand_env;
+
+const INCLUDE: &str = "include";
+const INCLUDE_IF: &str = "include_if";
+
+struct Include {
+    pattern: String,
+    optional: bool,
+}
+
+fn includes(value: &Value, file: &Path) -> Result<Vec<Include>, String> {
+    let invalid = || {
+        format!(
+            "[ERROR] {}: '{}' is a path, a table with a 'path', or an array of those",
+            file.display(),
+            INCLUDE
+        )
+    };
+    let entry = |item: &Value| match item {
+        Value::String(pattern) => Ok(Include {
+            pattern: pattern.clone(),
+            optional: false,
+        }),
+        Value::Table(table) => match (table.get("path"), table.get("optional")) {
+            (Some(Value::String(pattern)), None) => Ok(Include {
+                pattern: pattern.clone(),
+                optional: false,
+            }),
+            (Some(Value::String(pattern)), Some(Value::Boolean(optional))) => Ok(Include {
+                pattern: pattern.clone(),
+                optional: *optional,
+            }),
+            _ => Err(invalid()),
+        },
+        _ => Err(invalid()),
+    };
+    match value {
+        Value::Array(items) => items.iter().map(entry).collect(),
+        single => Ok(vec![entry(single)?]),
+    }
+}
+
+struct ConditionalInclude {
+    condition: Condition,
+    includes: Vec<Include>,
+}
+
+fn conditional_includes(value: &Value, file: &Path) -> Result<Vec<ConditionalInclude>, String> {
+    let invalid = |what: &str| format!("[ERROR] {}: '{}' {}", file.display(), INCLUDE_IF, what);
+    let entry = |item: &Value| {
+        let table = item
+            .as_table()
+            .ok_or_else(|| invalid("is a table or an array of tables"))?;
+        let optional = match table.get("optional") {
+            None => false,
+            Some(Value::Boolean(optional)) => *optional,
+            Some(_) => return Err(invalid("has 'optional' that is not a boolean")),
+        };
+        let patterns = match table.get("path") {
+            Some(Value::String(pattern)) => vec![pattern.clone()],
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| item.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| invalid("has a 'path' that is not a string"))?,
+            _ => return Err(invalid("needs a 'path' string or an array of them")),
+        };
+        Ok(ConditionalInclude {
+            condition: condition::parse(table, file)?,
+            includes: patterns
+                .into_iter()
+                .map(|pattern| Include { pattern, optional })
+                .collect(),
+        })
+    };
+    match value {
+        Value::Array(items) => items.iter().map(entry).collect(),
+        single => Ok(vec![entry(single)?]),
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+pub fn expand_path(pattern: &str) -> PathBuf {
+    let expanded = expand_env::expand_env_var(pattern);
+    match (expanded.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(expanded),
+    }
+}
+
+// Relative to the file that names it, not to wherever the wrapper happens to
+// be started from: a shared alias set checked out somewhere has to find its
+// own pieces however it is reached.
+fn resolve_pattern(pattern: &str, file: &Path) -> PathBuf {
+    let path = expand_path(pattern);
+    match file.parent() {
+        Some(directory) if path.is_relative() => directory.join(path),
+        _ => path,
+    }
+}
+
+fn included_files(include: &Include, file: &Path) -> Result<Vec<PathBuf>, String> {
+    let path = resolve_pattern(&include.pattern, file);
+    // A pattern is a question, and matching nothing is an answer to it. A
+    // path spelled out is a promise that the file is there, unless it says
+    // otherwise.
+    if glob::has_wildcards(&include.pattern) {
+        return Ok(glob::expand(&path));
+    }
+    if path.exists() {
+        return Ok(vec![path]);
+    }
+    if include.optional {
+        return Ok(vec![]);
+    }
+    Err(format!(
+        "[ERROR] {}: the included file {} does not exist (mark it optional = true if it may be missing)",
+        file.display(),
+        path.display()
+    ))
+}
+
+pub fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn read_included(
+    includes: &[Include],
+    file: &Path,
+    chain: &mut Vec<PathBuf>,
+    location: &Location,
+) -> Result<Vec<Layer>, String> {
+    let mut layers = vec![];
+    for include in includes {
+        for included in included_files(include, file)? {
+            layers.extend(read(&included, chain, location)?);
+        }
+    }
+    Ok(layers)
+}
+
+// The files a file includes come first, in the order they are listed, and the
+// file itself is merged on top of them: what it says for itself wins over
+// whatever it pulls in. A pattern contributes its matches in path order.
+//
+// Conditional includes go the other way round and are merged on top of the
+// file, in the order they are listed: they exist to change what the file says
+// in the places they apply to.
+//
+// Each file stays a layer of its own rather than being merged here, so that
+// whatever turns out to be wrong with a key can be traced to the file it is in.
+fn read(file: &Path, chain: &mut Vec<PathBuf>, location: &Location) -> Result<Vec<Layer>, String> {
+    let identity = canonical(file);
+    if let Some(start) = chain.iter().position(|seen| seen == &identity) {
+        let cycle: Vec<String> = chain[start..]
+            .iter()
+            .chain(std::iter::once(&identity))
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(format!("[ERROR] include cycle: {}", cycle.join(" -> ")));
+    }
+
+    let text = read_text(file)?;
+    let mut value = parse_value(&text, file)?;
+    let (listed, conditional) = match value.as_table_mut() {
+        Some(table) => (table.remove(INCLUDE), table.remove(INCLUDE_IF)),
+        None => (None, None),
+    };
+    let listed = match listed {
+        Some(listed) => includes(&listed, file)?,
+        None => vec![],
+    };
+    let conditional = match conditional {
+        Some(conditional) => conditional_includes(&conditional, file)?,
+        None => vec![],
+    };
+
+    chain.push(identity);
+    let mut layers = read_included(&listed, file, chain, location)?;
+    layers.push(Layer::new(file.to_path_buf(), text, value));
+    for entry in conditional
+        .iter()
+        .filter(|entry| entry.condition.holds(location))
+    {
+        layers.extend(read_included(&entry.includes, file, chain, location)?);
+    }
+    chain.pop();
+
+    Ok(layers)
+}
+
+// Every file a config names, the conditional ones whether or not they apply
+// where this runs, split into those merged below it and those merged on top.
+pub fn named_files(value: &Value, file: &Path) -> Result<(Vec<PathBuf>, Vec<PathBuf>), String> {
+    let mut below = vec![];
+    if let Some(listed) = value.get(INCLUDE) {
+        for include in includes(listed, file)? {
+            below.extend(included_files(&include, file)?);
+        }
+    }
+    let mut above = vec![];
+    if let Some(conditional) = value.get(INCLUDE_IF) {
+        for entry in conditional_includes(conditional, file)? {
+            for include in &entry.includes {
+                above.extend(included_files(include, file)?);
+            }
+        }
+    }
+    Ok((below, above))
+}
+
+pub fn read_with_includes(file: &Path) -> Result<Vec<Layer>, String> {
+    read(file, &mut Vec::new(), &Location::current())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::model::{Model, Node};
+
+    fn write(path: &Path, contents: &str) {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, contents).unwrap();
+    }
+
+    fn merged(layers: Vec<Layer>) -> Model {
+        Model::load(layers).0
+    }
+
+    fn alias(model: &Model, name: &str) -> Option<String> {
+        match model.aliases.members.get(name)? {
+            Node::Alias(alias) => Some(alias.display.clone()),
+            Node::Group(_) => None,
+        }
+    }
+
+    fn consumed(model: &Model, key: &str) -> bool {
+        model
+            .layers
+            .iter()
+            .all(|layer| layer.value().get(key).is_none())
+    }
+
+    #[test]
+    fn an_included_file_is_found_relative_to_the_file_naming_it() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = directory.path().join("wrapper").join("config.toml");
+        write(&config, "include = [\"../shared/docker.toml\"]\n");
+        write(
+            &directory.path().join("shared").join("docker.toml"),
+            "[alias]\nps = \"container ls\"\n",
+        );
+
+        let value = merged(read_with_includes(&config).unwrap());
+
+        assert_eq!(Some("container ls".to_string()), alias(&value, "ps"));
+        assert!(consumed(&value, INCLUDE), "the key is consumed");
+    }
+
+    #[test]
+    fn the_including_file_wins_over_what_it_includes_and_later_includes_over_earlier() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = directory.path().join("config.toml");
+        write(
+            &config,
+            "include = [\"first.toml\", \"second.toml\"]\n[alias]\nco = \"checkout mine\"\n",
+        );
+        write(
+            &directory.path().join("first.toml"),
+            "[alias]\nco = \"checkout first\"\nst = \"status first\"\nlg = \"log first\"\n",
+        );
+        write(
+            &directory.path().join("second.toml"),
+            "[alias]\nst = \"status second\"\n",
+        );
+
+        let value = merged(read_with_includes(&config).unwrap());
+
+        assert_eq!(Some("checkout mine".to_string()), alias(&value, "co"));
+        assert_eq!(Some("status second".to_string()), alias(&value, "st"));
+        assert_eq!(Some("log first".to_string()), alias(&value, "lg"));
+    }
+
+    #[test]
+    fn a_pattern_includes_its_matches_in_path_order() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = directory.path().join("config.toml");
+        write(&config, "include = \"sets/*.toml\"\n");
+        write(
+            &directory.path().join("sets").join("a.toml"),
+            "[alias]\nco = \"checkout a\"\n",
+        );
+        write(
+            &directory.path().join("sets").join("b.toml"),
+            "[alias]\nco = \"checkout b\"\n",
+        );
+
+        let value = merged(read_with_includes(&config).unwrap());
+
+        assert_eq!(Some("checkout b".to_string()), alias(&value, "co"));
+    }
+
+    #[test]
+    fn a_pattern_matching_nothing_is_not_an_error() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = directory.path().join("config.toml");
+        write(&config, "include = [\"sets/*.toml\"]\n");
+
+        assert!(read_with_includes(&config).is_ok());
+    }
+
+    #[test]
+    fn a_missing_file_is_an_error_unless_it_is_optional() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = directory.path().join("config.toml");
+
+        write(&config, "include = [\"local.toml\"]\n");
+        let error = read_with_includes(&config)
+            .map(|_| ())
+            .expect_err("the file is not there");
+        assert!(error.contains("local.toml"), "unexpected error: {}", error);
+
+        write(
+            &config,
+            "include = [{ path = \"local.toml\", optional = true }]\n",
+        );
+        assert!(read_with_includes(&config).is_ok());
+    }
+
+    #[test]
+    fn includes_are_followed_through_included_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = directory.path().join("config.toml");
+        write(&config, "include = [\"shared/all.toml\"]\n");
+        write(
+            &directory.path().join("shared").join("all.toml"),
+            "include = [\"k8s.toml\"]\n",
+        );
+        write(
+            &directory.path().join("shared").join("k8s.toml"),
+            "[alias]\npods = \"get pods\"\n",
+        );
+
+        let value = merged(read_with_includes(&config).unwrap());
+
+        assert_eq!(Some("get pods".to_string()), alias(&value, "pods"));
+    }
+
+    #[test]
+    fn an_include_cycle_is_reported_with_every_file_in_it() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = directory.path().join("config.toml");
+        write(&config, "include = [\"a.toml\"]\n");
+        write(&directory.path().join("a.toml"), "include = [\"b.toml\"]\n");
+        write(&directory.path().join("b.toml"), "include = [\"a.toml\"]\n");
+
+        let error = read_with_includes(&config)
+            .map(|_| ())
+            .expect_err("a.toml and b.toml include each other");
+
+        assert!(
+            error.contains("include cycle"),
+            "unexpected error: {}",
+            error
+        );
+        let a = canonical(&directory.path().join("a.toml"));
+        let b = canonical(&directory.path().join("b.toml"));
+        assert!(
+            error.ends_with(&format!(
+                "{} -> {} -> {}",
+                a.display(),
+                b.display(),
+                a.display()
+            )),
+            "unexpected error: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn an_include_that_is_not_a_path_is_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = directory.path().join("config.toml");
+        write(&config, "include = [42]\n");
+
+        let error = read_with_includes(&config)
+            .map(|_| ())
+            .expect_err("42 is not a path");
+        assert!(error.contains("'include'"), "unexpected error: {}", error);
+    }
+
+    fn read_at(config: &Path, cwd: &Path) -> Result<Vec<Layer>, String> {
+        read(
+            config,
+            &mut Vec::new(),
+            &Location::at(Some(cwd.to_path_buf())),
+        )
+    }
+
+    #[test]
+    fn a_conditional_include_is_merged_on_top_where_its_condition_holds() {
+        let directory = tempfile::tempdir().unwrap();
+        let work = directory.path().join("work");
+        let config = directory.path().join("config.toml");
+        write(
+            &config,
+            &format!(
+                "[alias]\nco = \"checkout\"\n\n[[include_if]]\ncwd = \"{}/\"\npath = \"work.toml\"\n",
+                work.display().to_string().replace('\\', "/")
+            ),
+        );
+        write(
+            &directory.path().join("work.toml"),
+            "[alias]\nco = \"checkout --no-track\"\n",
+        );
+
+        let inside = merged(read_at(&config, &work.join("client")).unwrap());
+        let outside = merged(read_at(&config, directory.path()).unwrap());
+
+        assert_eq!(
+            Some("checkout --no-track".to_string()),
+            alias(&inside, "co")
+        );
+        assert_eq!(Some("checkout".to_string()), alias(&outside, "co"));
+        assert!(consumed(&inside, INCLUDE_IF), "the key is consumed");
+    }
+
+    #[test]
+    fn a_conditional_include_that_does_not_apply_is_not_read() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = directory.path().join("config.toml");
+        write(
+            &config,
+            "[[include_if]]\ncwd = \"/nowhere/**\"\npath = \"missing.toml\"\n",
+        );
+
+        assert!(read_at(&config, directory.path()).is_ok());
+    }
+
+    #[test]
+    fn a_conditional_include_without_a_path_is_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = directory.path().join("config.toml");
+        write(&config, "[[include_if]]\ncwd = \"/**\"\n");
+
+        let error = read_at(&config, directory.path())
+            .map(|_| ())
+            .expect_err("there is nothing to include");
+        assert!(error.contains("'path'"), "unexpected error: {}", error);
+    }
+}
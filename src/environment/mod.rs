@@ -1,6 +1,6 @@
 use crate::environment::autodetect_executable::{OsFileSystemWrapper, autodetect_executable};
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub mod autodetect_executable;
 pub mod expand_env;
@@ -10,6 +10,8 @@ pub struct Environment {
     executable_dir: PathBuf,
     args: Vec<String>,
     shell: Option<String>,
+    system_config_dir: Option<PathBuf>,
+    user_config_dir: Option<PathBuf>,
 }
 
 impl Environment {
@@ -21,6 +23,15 @@ impl Environment {
         &self.executable_dir
     }
 
+    // The program the wrapper stands in for, whatever its file is called on
+    // disk: git.exe names its config files after git, just as git does.
+    pub fn program_name(&self) -> &str {
+        Path::new(&self.executable_name)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(&self.executable_name)
+    }
+
     pub fn executable_path(&self) -> PathBuf {
         self.executable_dir.join(&self.executable_name)
     }
@@ -44,6 +55,17 @@ impl Environment {
         })
     }
 
+    // Where the machine and the user keep config files for the wrappers they
+    // run, one file per program. Either may be unknown: a service can run
+    // without HOME, windows without APPDATA.
+    pub fn system_config_dir(&self) -> Option<&Path> {
+        self.system_config_dir.as_deref()
+    }
+
+    pub fn user_config_dir(&self) -> Option<&Path> {
+        self.user_config_dir.as_deref()
+    }
+
     pub fn try_detect_executable(&self) -> Option<String> {
         let path_var = env::var("PATH").unwrap_or_default();
         autodetect_executable(
@@ -66,8 +88,44 @@ impl Environment {
             executable_dir,
             args: vec!["test".to_string()],
             shell: Some("/bin/sh".to_string()),
+            // Never the real ones: a test has no business reading, let alone
+            // writing, the config of whoever runs it.
+            system_config_dir: None,
+            user_config_dir: None,
         }
     }
+
+    pub fn with_config_dirs(self, system: PathBuf, user: PathBuf) -> Self {
+        Environment {
+            system_config_dir: Some(system),
+            user_config_dir: Some(user),
+            ..self
+        }
+    }
+}
+
+pub(crate) fn non_empty_var(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+fn system_config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        non_empty_var("PROGRAMDATA").map(|directory| directory.join("alias"))
+    } else {
+        Some(PathBuf::from("/etc/alias"))
+    }
+}
+
+fn user_config_dir() -> Option<PathBuf> {
+    let directory = if cfg!(windows) {
+        non_empty_var("APPDATA")
+    } else {
+        non_empty_var("XDG_CONFIG_HOME")
+            .or_else(|| non_empty_var("HOME").map(|home| home.join(".config")))
+    };
+    directory.map(|directory| directory.join("alias"))
 }
 
 pub fn system_environment() -> Result<Environment, String> {
@@ -86,6 +144,8 @@ pub fn system_environment() -> Result<Environment, String> {
         executable_dir,
         args: env::args().collect(),
         shell: env::var("SHELL").ok(),
+        system_config_dir: system_config_dir(),
+        user_config_dir: user_config_dir(),
     })
 }
 
@@ -99,6 +159,8 @@ mod tests {
             executable_dir: PathBuf::from("/bin"),
             args,
             shell: Some("/bin/sh".to_string()),
+            system_config_dir: None,
+            user_config_dir: None,
         }
     }
 
@@ -118,6 +180,16 @@ mod tests {
         );
     }
 
+    #[test]
+    fn the_program_name_leaves_the_extension_out() {
+        let environment = Environment {
+            executable_name: "git.exe".to_string(),
+            ..environment_with(vec![])
+        };
+        assert_eq!("git", environment.program_name());
+        assert_eq!("git", environment_with(vec![]).program_name());
+    }
+
     #[test]
     fn call_arguments_drop_the_name_the_wrapper_was_called_by() {
         let environment = environment_with(vec!["git".to_string(), "co".to_string()]);
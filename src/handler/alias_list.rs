@@ -1,68 +1,251 @@
-use crate::config::{AliasNode, Configuration, get_config_path};
+use crate::config::{AliasNode, Configuration, Details, get_config_paths};
 use crate::environment::Environment;
 use crate::handler::{Handler, passthrough};
 
+// Shown under the entry it belongs to, marked the way a toml comment is, so
+// that it cannot be mistaken for a member of a group.
+fn print_details(details: &Details, indent: &str) {
+    if let Some(description) = &details.description {
+        println!("{}# {}", indent, description);
+    }
+    if let Some(example) = &details.example {
+        println!("{}# example: {}", indent, example);
+    }
+    if !details.tags.is_empty() {
+        println!("{}# tags: {}", indent, details.tags.join(", "));
+    }
+}
+
 fn print_tree(entries: &[(String, AliasNode)], indent: &str) {
+    let nested = format!("{}  ", indent);
     let mut printed = false;
     for (name, node) in entries {
-        if let AliasNode::Leaf(value) = node {
+        if let AliasNode::Leaf(value, details) = node {
             println!("{}{} = {}", indent, name, value);
+            print_details(details, &nested);
             printed = true;
         }
     }
     for (name, node) in entries {
-        if let AliasNode::Group(children) = node {
+        if let AliasNode::Group(children, default, details) = node {
             if printed {
                 println!();
             }
             println!("{}{}:", indent, name);
-            print_tree(children, &format!("{}  ", indent));
+            print_details(details, &nested);
+            // In brackets, which no member name can be mistaken for, and
+            // first, since it is what the group does on its own.
+            if let Some((value, details)) = default {
+                println!("{}(default) = {}", nested, value);
+                print_details(details, &format!("{}  ", nested));
+            }
+            print_tree(children, &nested);
             printed = true;
         }
     }
 }
 
-// The config file is created on the first launch, unless the directory the
-// wrapper sits in cannot be written to, and then there is nowhere to define an
-// alias in. The wrapper keeps forwarding commands either way, so this is the
-// one place the difference between 'no aliases configured' and 'no config file
-// at all' can be told.
+// A tag on a group covers every member of it, the way the group's description
+// describes all of them: a group tagged 'db' is listed whole.
+fn filter_by_tag(entries: Vec<(String, AliasNode)>, tag: &str) -> Vec<(String, AliasNode)> {
+    entries
+        .into_iter()
+        .filter_map(|(name, node)| match node {
+            AliasNode::Leaf(_, ref details) if details.tags.iter().any(|t| t == tag) => {
+                Some((name, node))
+            }
+            AliasNode::Leaf(..) => None,
+            AliasNode::Group(_, _, ref details) if details.tags.iter().any(|t| t == tag) => {
+                Some((name, node))
+            }
+            AliasNode::Group(children, default, details) => {
+                let children = filter_by_tag(children, tag);
+                let default = default.filter(|(_, details)| details.tags.iter().any(|t| t == tag));
+                if children.is_empty() && default.is_none() {
+                    None
+                } else {
+                    Some((name, AliasNode::Group(children, default, details)))
+                }
+            }
+        })
+        .collect()
+}
+
+// The config file is created on the first launch, unless no directory it can
+// go to can be written to, and then there is nowhere to define an alias in.
+// The wrapper keeps forwarding commands either way, so this is the one place
+// the difference between 'no aliases configured' and 'no config file at all'
+// can be told.
 fn missing_config_report(environment: &Environment) -> Option<String> {
-    let config_file_path = get_config_path(environment.executable_dir());
-    if config_file_path.exists() {
+    let config_file_paths = get_config_paths(environment);
+    if config_file_paths.iter().any(|path| path.exists()) {
         return None;
     }
+    let names: Vec<String> = config_file_paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
     Some(format!(
-        "no aliases: {} does not exist and could not be created",
-        config_file_path.display()
+        "no aliases: none of {} exists, and none could be created",
+        names.join(", ")
     ))
 }
 
-pub struct AliasListHandler {}
+pub struct AliasListHandler {
+    tag: Option<String>,
+}
+
+// Patterns, flags and defaults in sections of their own, after the aliases:
+// none of them is a name to type as a command, and patterns are listed in the
+// order they are tried, since the first one that matches wins.
+fn print_section(title: &str, entries: &[(String, AliasNode)], after_others: bool) {
+    if entries.is_empty() {
+        return;
+    }
+    if after_others {
+        println!();
+    }
+    println!("[{}]", title);
+    print_tree(entries, "  ");
+}
 
 impl Handler for AliasListHandler {
     fn handle(&self, environment: &Environment, configuration: &Configuration) {
-        let entries = configuration.list_alias_tree();
-        if entries.is_empty() {
+        let mut entries = configuration.list_alias_tree();
+        let mut patterns = configuration.list_pattern_aliases();
+        let mut flags = configuration.list_flag_aliases();
+        let mut defaults = configuration.list_defaults();
+        // Filtered after the check: a tag nothing carries is not a missing
+        // config file.
+        if entries.is_empty() && patterns.is_empty() && flags.is_empty() && defaults.is_empty() {
             // On stderr, so that the listing itself stays pipeable.
             if let Some(report) = missing_config_report(environment) {
                 eprintln!("{}", report);
             }
         }
+        // Only what was asked for: the target has no idea what our tags are,
+        // and its own answer to --aliases would be noise here.
+        if let Some(tag) = &self.tag {
+            entries = filter_by_tag(entries, tag);
+            patterns = filter_by_tag(patterns, tag);
+            // Flag aliases and defaults cannot carry tags, so none of them
+            // has the one asked for.
+            flags.clear();
+            defaults.clear();
+        }
         print_tree(&entries, "");
-        passthrough::try_passthrough(environment, configuration, &["--aliases"]);
+        print_section("pattern_alias", &patterns, !entries.is_empty());
+        print_section(
+            "flag_alias",
+            &flags,
+            !entries.is_empty() || !patterns.is_empty(),
+        );
+        print_section(
+            "defaults",
+            &defaults,
+            !entries.is_empty() || !patterns.is_empty() || !flags.is_empty(),
+        );
+        if self.tag.is_none() {
+            passthrough::try_passthrough(environment, configuration, &["--aliases"]);
+        }
     }
 }
 
 impl AliasListHandler {
     pub fn new() -> AliasListHandler {
-        AliasListHandler {}
+        AliasListHandler { tag: None }
+    }
+
+    pub fn with_tag(tag: String) -> AliasListHandler {
+        AliasListHandler { tag: Some(tag) }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::get_config_path;
+
+    fn leaf(tags: &[&str]) -> AliasNode {
+        AliasNode::Leaf(
+            "value".to_string(),
+            Details {
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                ..Details::default()
+            },
+        )
+    }
+
+    fn group(children: Vec<(String, AliasNode)>, tags: &[&str]) -> AliasNode {
+        AliasNode::Group(
+            children,
+            None,
+            Details {
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                ..Details::default()
+            },
+        )
+    }
+
+    // Every entry by its full path, the way it is typed on the command line.
+    fn names(entries: &[(String, AliasNode)]) -> Vec<String> {
+        let mut names = Vec::new();
+        for (name, node) in entries {
+            names.push(name.clone());
+            if let AliasNode::Group(children, ..) = node {
+                for member in self::names(children) {
+                    names.push(format!("{} {}", name, member));
+                }
+            }
+        }
+        names
+    }
+
+    #[test]
+    fn only_aliases_carrying_the_tag_are_kept() {
+        let entries = vec![
+            ("psql".to_string(), leaf(&["db"])),
+            ("ps".to_string(), leaf(&["ops"])),
+            ("plain".to_string(), leaf(&[])),
+        ];
+
+        assert_eq!(vec!["psql"], names(&filter_by_tag(entries, "db")));
+    }
+
+    #[test]
+    fn a_group_is_kept_for_the_members_that_carry_the_tag() {
+        let entries = vec![(
+            "docker".to_string(),
+            group(
+                vec![
+                    ("pg".to_string(), leaf(&["db"])),
+                    ("ps".to_string(), leaf(&[])),
+                ],
+                &[],
+            ),
+        )];
+
+        assert_eq!(
+            vec!["docker", "docker pg"],
+            names(&filter_by_tag(entries, "db"))
+        );
+    }
+
+    #[test]
+    fn a_group_carrying_the_tag_is_kept_whole() {
+        let entries = vec![
+            (
+                "db".to_string(),
+                group(vec![("dump".to_string(), leaf(&[]))], &["db"]),
+            ),
+            (
+                "docker".to_string(),
+                group(vec![("ps".to_string(), leaf(&[]))], &[]),
+            ),
+        ];
+
+        assert_eq!(vec!["db", "db dump"], names(&filter_by_tag(entries, "db")));
+    }
 
     #[test]
     fn a_config_file_that_is_not_there_is_reported_by_its_path() {
@@ -0,0 +1,41 @@
+use crate::config::{Configuration, check_configuration};
+use crate::environment::Environment;
+use crate::handler::Handler;
+
+pub struct CheckHandler {}
+
+// Everything is reported at once, one problem per line in the file:line:column
+// form editors and CI logs know how to link, and the exit code says whether
+// there was anything to report.
+impl Handler for CheckHandler {
+    fn handle(&self, environment: &Environment, _configuration: &Configuration) {
+        let (checked, problems) = check_configuration(environment);
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        let files = if checked == 1 { "file" } else { "files" };
+        if problems.is_empty() {
+            println!("{} config {} checked, no problems", checked, files);
+        } else {
+            eprintln!(
+                "{} config {} checked, {} problem(s)",
+                checked,
+                files,
+                problems.len()
+            );
+            std::process::exit(1);
+        }
+    }
+
+    // The configuration is what is being checked: one that cannot be loaded
+    // is the case this is for, not a reason to stop before it.
+    fn needs_configuration(&self) -> bool {
+        false
+    }
+}
+
+impl CheckHandler {
+    pub fn new() -> CheckHandler {
+        CheckHandler {}
+    }
+}
@@ -1,39 +1,182 @@
-use crate::config::Alias::{RegularAlias, ShellAlias};
-use crate::config::Configuration;
+use crate::config::{Alias, Stage};
+use crate::config::{CallEnv, Configuration};
 use crate::environment::Environment;
 use crate::handler::{Handler, get_executable};
 use crate::process::CallContext;
 use crate::{config, environment, process};
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 
-fn get_call_context(
+// Set, it makes the wrapper a plain forwarder, its own flags included: for
+// scripts and CI, which want the target as it is on every machine, whatever
+// aliases the people who run them have.
+const DISABLE: &str = "ALIAS_DISABLE";
+
+const RAW: &str = "--alias-raw";
+
+// One call past the aliases, for when one of them shadows a subcommand of the
+// target. The backslash is what a shell uses to skip its own aliases; it has
+// to be quoted or doubled to get through the shell, though, which the flag
+// does not.
+fn escaped_arguments(call_arguments: &[String]) -> Option<Vec<String>> {
+    let (first, rest) = call_arguments.split_first()?;
+    if first == RAW {
+        return Some(rest.to_vec());
+    }
+    let name = first.strip_prefix('\\').filter(|name| !name.is_empty())?;
+    Some(
+        std::iter::once(name.to_string())
+            .chain(rest.iter().cloned())
+            .collect(),
+    )
+}
+
+// What goes to the target untouched, if anything does: no alias, default or
+// flag alias applies to it.
+pub fn raw_arguments(call_arguments: &[String]) -> Option<Vec<String>> {
+    if env::var_os(DISABLE).is_some() {
+        return Some(call_arguments.to_vec());
+    }
+    escaped_arguments(call_arguments)
+}
+
+// How the calls of one alias go together.
+enum Chaining {
+    // One after another, stopping at the first that fails unless told not to.
+    Steps { continue_on_error: bool },
+    // All at the same time, the output of each one the input of the next.
+    Pipe,
+}
+
+// What a call of the wrapper comes to: one run of the target, or of a shell,
+// or one run for every step of a multi-step alias or stage of a pipe.
+struct Calls {
+    contexts: Vec<CallContext>,
+    chaining: Chaining,
+    // Only in a dry run: the commands that would have set variables.
+    not_run: BTreeMap<String, String>,
+}
+
+impl Calls {
+    fn single(call_context: CallContext) -> Calls {
+        Calls {
+            contexts: vec![call_context],
+            chaining: Chaining::Steps {
+                continue_on_error: false,
+            },
+            not_run: BTreeMap::new(),
+        }
+    }
+
+    // Every step or stage runs with the same variables, in the same
+    // directory: they are one alias, resolved once.
+    fn with_env(self, env: CallEnv) -> Calls {
+        let contexts = self
+            .contexts
+            .into_iter()
+            .map(|call_context| CallContext {
+                env: env.vars.clone(),
+                secret: env.secret.clone(),
+                cwd: env.cwd.clone(),
+                ..call_context
+            })
+            .collect();
+        Calls {
+            contexts,
+            not_run: env.not_run,
+            ..self
+        }
+    }
+}
+
+// Any other program is run as it is named: what makes the target's calls
+// different, flag aliases and run_as_shell, is the target's alone.
+fn call_a_program(program: String, args: Vec<String>) -> CallContext {
+    CallContext {
+        executable: program,
+        args,
+        env: BTreeMap::new(),
+        secret: BTreeSet::new(),
+        cwd: None,
+    }
+}
+
+fn get_calls(
     environment: &Environment,
     configuration: &Configuration,
-) -> Result<CallContext, String> {
+    raw: Option<&[String]>,
+) -> Result<Calls, String> {
     let call_arguments = environment.call_arguments();
     let executable = get_executable(environment, configuration)?.ok_or(format!(
         "Cannot autodetect executable: {}",
         environment.executable_name()
     ))?;
 
-    match configuration.resolve_alias(call_arguments)? {
-        Some((alias, consumed)) => {
-            let remaining = &call_arguments[consumed..];
-            match alias {
-                ShellAlias(cmd) => handle_shell_alias(remaining, environment.shell()?, cmd),
-                RegularAlias(mut arguments) => {
-                    arguments.extend_from_slice(remaining);
-                    call_the_target(configuration, environment, &executable, arguments)
-                }
-            }
+    if let Some(arguments) = raw {
+        return call_the_target(configuration, environment, &executable, arguments.to_vec())
+            .map(Calls::single);
+    }
+
+    // Flag aliases are rewritten in whatever goes to the target, expanded or
+    // not. What a shell alias gets is left alone: its command can run any
+    // program, and the flags of this target mean nothing to the others.
+    // Defaults only go to the target's own subcommands: an alias says what it
+    // runs in full already.
+    // A dry run shows which commands would set variables, without running
+    // them: one could prompt, or do anything else a command does.
+    let (alias, env) = configuration.resolve_alias(call_arguments, !dry_run())?;
+    let calls = match alias {
+        Some(Alias::Shell(cmd, arguments)) => {
+            Calls::single(handle_shell_alias(&arguments, environment.shell()?, cmd)?)
         }
-        None => call_the_target(
+        Some(Alias::Regular(arguments)) => Calls::single(call_the_target(
             configuration,
             environment,
             &executable,
-            call_arguments.to_vec(),
-        ),
-    }
+            configuration.rewrite_flags(arguments),
+        )?),
+        Some(Alias::Steps {
+            steps,
+            continue_on_error,
+        }) => Calls {
+            contexts: steps
+                .into_iter()
+                .map(|arguments| {
+                    call_the_target(
+                        configuration,
+                        environment,
+                        &executable,
+                        configuration.rewrite_flags(arguments),
+                    )
+                })
+                .collect::<Result<_, _>>()?,
+            chaining: Chaining::Steps { continue_on_error },
+            not_run: BTreeMap::new(),
+        },
+        Some(Alias::Pipe(stages)) => Calls {
+            contexts: stages
+                .into_iter()
+                .map(|stage| match stage {
+                    Stage::Target(arguments) => call_the_target(
+                        configuration,
+                        environment,
+                        &executable,
+                        configuration.rewrite_flags(arguments),
+                    ),
+                    Stage::Program(program, args) => Ok(call_a_program(program, args)),
+                })
+                .collect::<Result<_, _>>()?,
+            chaining: Chaining::Pipe,
+            not_run: BTreeMap::new(),
+        },
+        None => Calls::single(call_the_target(
+            configuration,
+            environment,
+            &executable,
+            configuration.rewrite_flags(configuration.add_defaults(call_arguments.to_vec())),
+        )?),
+    };
+    Ok(calls.with_env(env))
 }
 
 // This is the only path a shell is looked up on outside of shell aliases, and
@@ -50,6 +193,9 @@ fn call_the_target(
         return Ok(CallContext {
             executable: executable.to_string(),
             args: arguments,
+            env: BTreeMap::new(),
+            secret: BTreeSet::new(),
+            cwd: None,
         });
     }
 
@@ -58,6 +204,9 @@ fn call_the_target(
     Ok(CallContext {
         executable: environment.shell()?.to_string(),
         args,
+        env: BTreeMap::new(),
+        secret: BTreeSet::new(),
+        cwd: None,
     })
 }
 
@@ -87,6 +236,9 @@ fn handle_shell_alias(
     Ok(CallContext {
         executable: shell.to_string(),
         args,
+        env: BTreeMap::new(),
+        secret: BTreeSet::new(),
+        cwd: None,
     })
 }
 
@@ -106,8 +258,10 @@ fn dry_run() -> bool {
 // arguments that contain spaces, and those are exactly the ones people come
 // here to look at.
 fn print_call_context(call_context: &CallContext) {
-    println!("dry run: {} is set, nothing is executed", DRY_RUN);
     println!("executable: {}", call_context.executable);
+    if let Some(cwd) = &call_context.cwd {
+        println!("cwd: {}", cwd.display());
+    }
     if call_context.args.is_empty() {
         println!("argv: none");
     } else {
@@ -118,6 +272,64 @@ fn print_call_context(call_context: &CallContext) {
     }
 }
 
+// The steps of an alias, and the stages of a pipe, share their variables,
+// which are shown once, after all of them.
+fn print_calls(calls: &Calls) {
+    println!("dry run: {} is set, nothing is executed", DRY_RUN);
+    let count = calls.contexts.len();
+    let part = match calls.chaining {
+        Chaining::Steps { .. } if count == 1 => None,
+        Chaining::Steps {
+            continue_on_error: true,
+        } => {
+            println!("steps: {}, each one run whatever the others did", count);
+            Some("step")
+        }
+        Chaining::Steps {
+            continue_on_error: false,
+        } => {
+            println!("steps: {}, stopping at the first that fails", count);
+            Some("step")
+        }
+        Chaining::Pipe => {
+            println!(
+                "pipe: {} stages, each one reading what the one before it writes",
+                count
+            );
+            Some("stage")
+        }
+    };
+    for (index, call_context) in calls.contexts.iter().enumerate() {
+        if let Some(part) = part {
+            println!("{} {}:", part, index + 1);
+        }
+        print_call_context(call_context);
+    }
+    if let Some(call_context) = calls.contexts.first() {
+        print_env(call_context, &calls.not_run);
+    }
+}
+
+fn print_env(call_context: &CallContext, not_run: &BTreeMap<String, String>) {
+    // Only what the config changes: the rest is the environment the wrapper
+    // was started in, which is there to look at anyway.
+    if !call_context.env.is_empty() {
+        println!("env:");
+        for (name, value) in &call_context.env {
+            match value {
+                Some(_) if not_run.contains_key(name) => {
+                    println!("  {} is set from command {}, not run", name, not_run[name])
+                }
+                Some(_) if call_context.secret.contains(name) => {
+                    println!("  {} is set from a command, not shown", name)
+                }
+                Some(value) => println!("  {}={}", name, value),
+                None => println!("  {} is unset", name),
+            }
+        }
+    }
+}
+
 fn run_as_shell(configuration: &Configuration) -> Result<bool, String> {
     match configuration.get_run_as_shell()? {
         None => Ok(false),
@@ -125,18 +337,27 @@ fn run_as_shell(configuration: &Configuration) -> Result<bool, String> {
     }
 }
 
-fn execute(environment: &environment::Environment, configuration: &config::Configuration) {
-    let call_context_result = get_call_context(environment, configuration);
-    match call_context_result {
-        Ok(call_context) => {
+fn execute(
+    environment: &environment::Environment,
+    configuration: &config::Configuration,
+    raw: Option<&[String]>,
+) {
+    match get_calls(environment, configuration, raw) {
+        Ok(calls) => {
             // Deliberately here and not in process::execute, which also serves
             // the passthrough behind --help and --aliases: there is nothing to
             // explain about that one.
             if dry_run() {
-                print_call_context(&call_context);
+                print_calls(&calls);
                 return;
             }
-            match process::execute(&call_context) {
+            let result = match calls.chaining {
+                Chaining::Steps { continue_on_error } => {
+                    process::execute(&calls.contexts, continue_on_error)
+                }
+                Chaining::Pipe => process::execute_pipe(&calls.contexts),
+            };
+            match result {
                 Ok(code) => process::exit(code),
                 Err(error) => {
                     eprintln!("{}", error);
@@ -156,17 +377,38 @@ fn execute(environment: &environment::Environment, configuration: &config::Confi
     }
 }
 
-pub struct DefaultHandler {}
+pub struct DefaultHandler {
+    raw: Option<Vec<String>>,
+}
 
 impl Handler for DefaultHandler {
     fn handle(&self, environment: &Environment, configuration: &Configuration) {
-        execute(environment, configuration)
+        match &self.raw {
+            Some(arguments) => execute(
+                environment,
+                &config::get_raw_configuration(environment),
+                Some(arguments),
+            ),
+            None => execute(environment, configuration, None),
+        }
+    }
+
+    // A call that bypasses the aliases reads the few settings it needs on its
+    // own, so that a config that cannot be loaded does not stand in its way.
+    fn needs_configuration(&self) -> bool {
+        self.raw.is_none()
     }
 }
 
 impl DefaultHandler {
     pub fn new() -> DefaultHandler {
-        DefaultHandler {}
+        DefaultHandler { raw: None }
+    }
+
+    pub fn raw(arguments: Vec<String>) -> DefaultHandler {
+        DefaultHandler {
+            raw: Some(arguments),
+        }
     }
 }
 
@@ -196,6 +438,33 @@ mod tests {
         );
     }
 
+    fn escaped(arguments: &[&str]) -> Option<Vec<String>> {
+        let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+        escaped_arguments(&arguments)
+    }
+
+    #[test]
+    fn a_leading_backslash_or_the_raw_flag_escapes_the_call() {
+        assert_eq!(
+            Some(vec!["co".to_string(), "-b".to_string()]),
+            escaped(&["\\co", "-b"])
+        );
+        assert_eq!(
+            Some(vec!["co".to_string()]),
+            escaped(&["--alias-raw", "co"])
+        );
+        assert_eq!(Some(Vec::new()), escaped(&["--alias-raw"]));
+    }
+
+    // Only the first argument escapes a call, and only when it escapes
+    // something: a lone backslash is an argument like any other.
+    #[test]
+    fn a_backslash_anywhere_else_is_an_argument_like_any_other() {
+        assert_eq!(None, escaped(&["co", "\\co"]));
+        assert_eq!(None, escaped(&["\\"]));
+        assert_eq!(None, escaped(&[]));
+    }
+
     // The quotes around $@ are what keeps this one argument rather than two.
     #[test]
     fn an_argument_containing_spaces_stays_a_single_argument() {
@@ -18,11 +18,22 @@ impl Handler for HelpHandler {
         println!();
         println!("OPTIONS:");
         println!("    --aliases    List all configured aliases");
+        println!("    --aliases --tag <TAG>");
+        println!("                 List only the aliases tagged <TAG>");
+        println!("    --alias-check");
+        println!("                 Check every config file, report all problems, exit 1 if any");
+        println!("    --alias-trust");
+        println!("                 Trust .alias/<tool>.toml of the current project as it is now");
+        println!("    --alias-raw <ARGS...>, \\<ARG> <ARGS...>");
+        println!("                 Pass this one call on to the tool as it is, past the aliases");
         println!("    --version    Print version");
         println!("    --help       Print this help message");
         println!();
         println!("ENVIRONMENT:");
+        println!("    ALIAS_CONFIG     A config file merged on top of all the others");
         println!("    ALIAS_DRY_RUN    Print the command that would run, execute nothing");
+        println!("    ALIAS_DISABLE    Pass every call on to the tool as it is, flags included");
+        println!("    XDG_DATA_HOME    Where the list of trusted project files is kept");
         println!();
         passthrough::try_passthrough(environment, configuration, &["--help"]);
     }
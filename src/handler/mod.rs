@@ -5,10 +5,12 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 pub mod alias_list;
+pub mod check;
 pub mod default;
 pub mod error;
 pub mod help;
 pub mod passthrough;
+pub mod trust;
 pub mod version;
 
 // Where the target program is: named by the config, or looked up in PATH when
@@ -58,6 +60,13 @@ pub fn version_line() -> String {
 
 pub trait Handler {
     fn handle(&self, environment: &environment::Environment, configuration: &config::Configuration);
+
+    // A handler that works on the config files themselves, or on none of them,
+    // is run without loading them first: it gets an empty configuration, and a
+    // broken file does not stand in its way.
+    fn needs_configuration(&self) -> bool {
+        true
+    }
 }
 
 #[cfg(test)]
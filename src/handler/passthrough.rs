@@ -12,6 +12,9 @@ pub fn try_passthrough(environment: &Environment, configuration: &Configuration,
     let call_context = CallContext {
         executable,
         args: args.iter().map(|s| s.to_string()).collect(),
+        env: Default::default(),
+        secret: Default::default(),
+        cwd: None,
     };
 
     let _ = process::try_execute_forwarded(&call_context);
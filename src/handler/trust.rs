@@ -0,0 +1,52 @@
+use std::env;
+
+use crate::config::{Configuration, project};
+use crate::environment::Environment;
+use crate::handler::Handler;
+
+pub struct TrustHandler {}
+
+// Trusts the project file the wrapper would read from here, as it is on disk
+// right now; there is no naming a file, so what gets trusted is exactly what
+// the next call is going to use.
+fn trust(environment: &Environment) -> Result<String, String> {
+    let cwd = env::current_dir().map_err(|e| format!("cannot get the current directory: {}", e))?;
+    let name = environment.program_name();
+    let file = project::find_project_file(&cwd, name).ok_or_else(|| {
+        format!(
+            "there is no .alias/{}.toml in {} or in any directory above it",
+            name,
+            cwd.display()
+        )
+    })?;
+    let store = project::store_path().ok_or(
+        "cannot find a place for the list of trusted files: \
+set XDG_DATA_HOME or HOME, or LOCALAPPDATA on Windows",
+    )?;
+    project::trust(&file, &store)?;
+    Ok(format!("trusted {}", file.display()))
+}
+
+impl Handler for TrustHandler {
+    fn handle(&self, environment: &Environment, _configuration: &Configuration) {
+        match trust(environment) {
+            Ok(message) => println!("{}", message),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // What it trusts is the project file, not the configuration, and loading
+    // that would only repeat the warning about the file not being trusted yet.
+    fn needs_configuration(&self) -> bool {
+        false
+    }
+}
+
+impl TrustHandler {
+    pub fn new() -> TrustHandler {
+        TrustHandler {}
+    }
+}
@@ -1,9 +1,11 @@
 use config::empty_configuration;
 use handler::Handler;
 use handler::alias_list::AliasListHandler;
+use handler::check::CheckHandler;
 use handler::default::DefaultHandler;
 use handler::error::ErrorHandler;
 use handler::help::HelpHandler;
+use handler::trust::TrustHandler;
 use handler::version::VersionHandler;
 
 mod config;
@@ -14,6 +16,12 @@ mod process;
 fn get_handler(environment: &environment::Environment) -> Box<dyn Handler> {
     let call_arguments = environment.call_arguments();
 
+    // Ahead of the wrapper's own flags: an escaped call is the target's
+    // entirely, and a disabled wrapper answers to nothing.
+    if let Some(arguments) = handler::default::raw_arguments(call_arguments) {
+        return Box::new(DefaultHandler::raw(arguments));
+    }
+
     let arg_count = call_arguments.len();
 
     if arg_count == 1 {
@@ -27,6 +35,18 @@ fn get_handler(environment: &environment::Environment) -> Box<dyn Handler> {
         if command == "--help" {
             return Box::new(HelpHandler::new());
         }
+        if command == "--alias-check" {
+            return Box::new(CheckHandler::new());
+        }
+        if command == "--alias-trust" {
+            return Box::new(TrustHandler::new());
+        }
+    }
+
+    if let [command, option, tag] = call_arguments {
+        if command == "--aliases" && option == "--tag" {
+            return Box::new(AliasListHandler::with_tag(tag.clone()));
+        }
     }
 
     Box::new(DefaultHandler::new())
@@ -46,11 +66,17 @@ fn main() {
         }
     };
 
+    let handler = get_handler(&environment);
+    if !handler.needs_configuration() {
+        handler.handle(&environment, &empty_configuration());
+        return;
+    }
+
     let configuration = config::get_configuration(&environment);
 
     match configuration {
         Ok(config) => {
-            get_handler(&environment).handle(&environment, &config);
+            handler.handle(&environment, &config);
         }
         Err(e) => {
             ErrorHandler::new(e).handle(&environment, &empty_configuration());
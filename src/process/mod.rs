@@ -1,11 +1,22 @@
 use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::io::Read;
-use std::process::{Command, ExitStatus, Stdio};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdout, Command, ExitStatus, Stdio};
+
+mod signals;
 
 pub struct CallContext {
     pub executable: String,
     pub args: Vec<String>,
+    // Set on top of the wrapper's own environment, or taken out of it when
+    // None.
+    pub env: BTreeMap<String, Option<String>>,
+    // Those of them that are never to be shown.
+    pub secret: BTreeSet<String>,
+    // Where the target runs, when not where the wrapper was started.
+    pub cwd: Option<PathBuf>,
 }
 
 fn format_command(executable: &str, args: &[String]) -> String {
@@ -51,9 +62,19 @@ Check the 'executable' entry of the config and any shell alias that names the wr
 
 fn command(context: &CallContext) -> Command {
     let mut command = Command::new(&context.executable);
-    command
-        .args(&context.args)
-        .env(NESTING, (nesting_level() + 1).to_string());
+    command.args(&context.args);
+    if let Some(cwd) = &context.cwd {
+        command.current_dir(cwd);
+    }
+    for (name, value) in &context.env {
+        match value {
+            Some(value) => command.env(name, value),
+            None => command.env_remove(name),
+        };
+    }
+    // After the config's own variables: the count is the wrapper's, whatever
+    // [env] says.
+    command.env(NESTING, (nesting_level() + 1).to_string());
     command
 }
 
@@ -92,6 +113,14 @@ fn run(context: &CallContext) -> Result<Option<i32>, String> {
 // Windows has no exec, so the target runs as a child process.
 #[cfg(not(unix))]
 fn run(context: &CallContext) -> Result<Option<i32>, String> {
+    run_child(context, &signals::Forwarding::new(1))
+}
+
+// The steps run one at a time, so they take turns in the one slot.
+fn run_child(
+    context: &CallContext,
+    forwarding: &signals::Forwarding,
+) -> Result<Option<i32>, String> {
     let mut output = command(context).spawn().map_err(|e| {
         format!(
             "Failed to execute process [{}]. {}",
@@ -99,18 +128,117 @@ fn run(context: &CallContext) -> Result<Option<i32>, String> {
             e
         )
     })?;
+    forwarding.started(0, output.id());
 
-    output.wait().map(exit_code).map_err(|e| {
+    let code = output.wait().map(exit_code).map_err(|e| {
         format!(
             "Failed to wait child process [{}]. {}",
             format_command(&context.executable, &context.args),
             e
         )
-    })
+    });
+    forwarding.finished(0);
+    code
 }
 
-pub fn execute(context: &CallContext) -> Result<Option<i32>, String> {
-    run(context)
+// A call is usually a single step, which replaces the wrapper. Every step but
+// the last runs as a child, so that the wrapper is still there to start the
+// next one; the last one replaces the wrapper all the same, unless an earlier
+// step failed and the rest only ran because of continue_on_error: the wrapper
+// then outlives it, to exit with the code of the first step that failed rather
+// than with that of the last.
+//
+// A step that runs as a child hears of a signal sent to the wrapper the way
+// the stages of a pipe do. The handlers stay in place for a last step that
+// replaces the wrapper, which is harmless: exec puts them back to default.
+pub fn execute(steps: &[CallContext], continue_on_error: bool) -> Result<Option<i32>, String> {
+    let Some((last, earlier)) = steps.split_last() else {
+        return Ok(Some(0));
+    };
+    if earlier.is_empty() {
+        return run(last);
+    }
+    let forwarding = signals::Forwarding::new(1);
+    let mut failed = None;
+    for step in earlier {
+        match run_child(step, &forwarding)? {
+            Some(0) => {}
+            code if !continue_on_error => return Ok(code),
+            code => {
+                failed.get_or_insert(code);
+            }
+        }
+    }
+    match failed {
+        None => run(last),
+        Some(code) => run_child(last, &forwarding).map(|_| code),
+    }
+}
+
+// The stages of a pipe run side by side as children, each one's stdout the
+// next one's stdin, the way a shell sets a pipeline up: the first reads the
+// wrapper's stdin, the last writes to its stdout, and all of them share its
+// stderr. The exit code is that of the last stage that failed, as under 'set
+// -o pipefail': a target failing at the head of a pipe is not hidden behind a
+// filter that had nothing to complain about. A stage killed by a signal counts
+// as failing with 128 and the signal's number, as exit_code has it, the same
+// as under a shell.
+pub fn execute_pipe(stages: &[CallContext]) -> Result<Option<i32>, String> {
+    let forwarding = signals::Forwarding::new(stages.len());
+    let mut children: Vec<Child> = Vec::new();
+    let mut input: Option<ChildStdout> = None;
+    for (index, stage) in stages.iter().enumerate() {
+        let mut command = command(stage);
+        if let Some(input) = input.take() {
+            command.stdin(input);
+        }
+        if index + 1 < stages.len() {
+            command.stdout(Stdio::piped());
+        }
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            // The stages already running would otherwise be left to find out
+            // from a pipe that nothing is ever going to read or write.
+            Err(e) => {
+                for child in &mut children {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+                return Err(format!(
+                    "Failed to execute process [{}]. {}",
+                    format_command(&stage.executable, &stage.args),
+                    e
+                ));
+            }
+        };
+        forwarding.started(index, child.id());
+        input = child.stdout.take();
+        children.push(child);
+    }
+
+    // A stage that cannot be waited for does not end the wait for the others:
+    // they are still running, and the wrapper is not to exit before they do.
+    let mut code = Some(0);
+    let mut error = None;
+    for (index, (child, stage)) in children.iter_mut().zip(stages).enumerate() {
+        let waited = child.wait().map(exit_code);
+        forwarding.finished(index);
+        match waited {
+            Ok(Some(0)) => {}
+            Ok(failed) => code = failed,
+            Err(e) => {
+                error.get_or_insert(format!(
+                    "Failed to wait child process [{}]. {}",
+                    format_command(&stage.executable, &stage.args),
+                    e
+                ));
+            }
+        }
+    }
+    match error {
+        Some(error) => Err(error),
+        None => Ok(code),
+    }
 }
 
 // The flag being forwarded may well be one the target knows nothing about
@@ -160,6 +288,26 @@ pub fn try_execute_forwarded(context: &CallContext) -> Result<Option<i32>, Strin
     Ok(code)
 }
 
+// What a command the config runs for a value prints, a secret most likely.
+// Only stdout is taken: stdin and stderr stay the terminal, so that a prompt
+// for a passphrase still reaches whoever is there to answer it. The problem
+// it reports names the command, but never what it printed.
+pub fn output_of(words: &[String]) -> Result<Vec<u8>, String> {
+    let (program, args) = words.split_first().ok_or_else(|| "is empty".to_string())?;
+    let output = Command::new(program)
+        .args(args)
+        .env(NESTING, (nesting_level() + 1).to_string())
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("could not be started: {}", e))?;
+    match exit_code(output.status) {
+        Some(0) => Ok(output.stdout),
+        Some(code) => Err(format!("failed with exit code {}", code)),
+        None => Err("was stopped before it finished".to_string()),
+    }
+}
+
 pub const COULD_NOT_RUN: i32 = 127;
 
 pub fn exit(code: Option<i32>) -> ! {
@@ -0,0 +1,106 @@
+// While the stages of a pipe run, or the steps of an alias before its last,
+// the wrapper stays behind to collect their exit codes, and whatever is sent
+// to stop it has to stop them as well: a supervisor, a CI timeout or a plain
+// 'kill' only know the wrapper's pid, and without this the children would run
+// on with nobody waiting for them.
+//
+// A signal from the terminal reaches the stages on its own, since they share
+// its foreground process group with the wrapper. They get it a second time
+// from here, which changes nothing for a program that is stopping already.
+#[cfg(unix)]
+mod unix {
+    use std::sync::atomic::{AtomicI32, AtomicPtr, AtomicUsize, Ordering::SeqCst};
+
+    const FORWARDED: [libc::c_int; 4] = [libc::SIGHUP, libc::SIGINT, libc::SIGQUIT, libc::SIGTERM];
+
+    // Read by the handler, which can neither lock nor allocate: there is a
+    // slot for every stage from the start, filled in as the stages start.
+    static PIDS: AtomicPtr<AtomicI32> = AtomicPtr::new(std::ptr::null_mut());
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    // For a stage that starts after the signal came: its slot was still
+    // empty when the handler looked.
+    static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+    extern "C" fn forward(signal: libc::c_int) {
+        RECEIVED.store(signal, SeqCst);
+        let pids = PIDS.load(SeqCst);
+        if pids.is_null() {
+            return;
+        }
+        for index in 0..COUNT.load(SeqCst) {
+            // SAFETY: the slots are leaked, so they outlive every call of the
+            // handler, and COUNT is never more than there are of them.
+            let pid = unsafe { &*pids.add(index) }.load(SeqCst);
+            if pid > 0 {
+                // SAFETY: kill is async-signal-safe.
+                unsafe { libc::kill(pid, signal) };
+            }
+        }
+    }
+
+    pub struct Forwarding {
+        pids: &'static [AtomicI32],
+    }
+
+    impl Forwarding {
+        // Installed before the first stage starts, and left in place until
+        // the wrapper exits, which it does as soon as the pipe is done.
+        pub fn new(stages: usize) -> Forwarding {
+            let pids: &'static [AtomicI32] =
+                Box::leak((0..stages).map(|_| AtomicI32::new(0)).collect());
+            COUNT.store(0, SeqCst);
+            PIDS.store(pids.as_ptr().cast_mut(), SeqCst);
+            COUNT.store(pids.len(), SeqCst);
+            for signal in FORWARDED {
+                // SAFETY: the action is fully initialised, and the handler
+                // only touches atomics and calls kill.
+                unsafe {
+                    let mut action: libc::sigaction = std::mem::zeroed();
+                    action.sa_sigaction = forward as extern "C" fn(libc::c_int) as usize;
+                    action.sa_flags = libc::SA_RESTART;
+                    libc::sigemptyset(&mut action.sa_mask);
+                    libc::sigaction(signal, &action, std::ptr::null_mut());
+                }
+            }
+            Forwarding { pids }
+        }
+
+        // The slot is filled before the check, and the handler records the
+        // signal before it reads the slots, so a signal that comes while a
+        // stage starts reaches it one way or the other, if not both.
+        pub fn started(&self, stage: usize, pid: u32) {
+            let pid = pid as i32;
+            self.pids[stage].store(pid, SeqCst);
+            let signal = RECEIVED.load(SeqCst);
+            if signal != 0 {
+                // SAFETY: a plain system call on a child of this process.
+                unsafe { libc::kill(pid, signal) };
+            }
+        }
+
+        // Emptied once the stage has been waited for: its pid is free to be
+        // given to a process that has nothing to do with the wrapper.
+        pub fn finished(&self, stage: usize) {
+            self.pids[stage].store(0, SeqCst);
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use unix::Forwarding;
+
+// Windows has no signals to pass on: Ctrl+C goes to every process attached to
+// the console, the stages included.
+#[cfg(not(unix))]
+pub struct Forwarding;
+
+#[cfg(not(unix))]
+impl Forwarding {
+    pub fn new(_stages: usize) -> Forwarding {
+        Forwarding
+    }
+
+    pub fn started(&self, _stage: usize, _pid: u32) {}
+
+    pub fn finished(&self, _stage: usize) {}
+}
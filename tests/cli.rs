@@ -30,7 +30,7 @@ const NESTING_LIMIT: u32 = 16;
 
 struct Wrapper {
     // Kept alive: dropping it removes the directory the wrapper lives in.
-    _directory: TempDir,
+    directory: TempDir,
     binary: PathBuf,
     target: PathBuf,
 }
@@ -60,7 +60,7 @@ impl Wrapper {
         fs::write(directory.path().join("config.toml"), config).expect("a config beside it");
 
         Wrapper {
-            _directory: directory,
+            directory,
             binary,
             target,
         }
@@ -84,12 +84,30 @@ impl Wrapper {
         execute(command)
     }
 
+    // Started from inside a project, with a list of trusted files that belongs
+    // to the test rather than to whoever runs it.
+    fn run_in(&self, project: &Path, arguments: &[&str]) -> Output {
+        let data = self.directory.path().join("data");
+        let mut command = self.command(arguments);
+        command.current_dir(project);
+        command.env("XDG_DATA_HOME", &data);
+        command.env("LOCALAPPDATA", &data);
+        execute(command)
+    }
+
     fn command(&self, arguments: &[&str]) -> Command {
         let mut command = Command::new(&self.binary);
         command.args(arguments);
         // Never inherit the shell of whoever runs the tests: on windows it may
         // be missing entirely, and the value only matters to shell aliases.
         command.env("SHELL", "/bin/sh");
+        // Nor their aliases: the user config is layered under the one beside
+        // the wrapper.
+        let user_config = self.directory.path().join("user-config");
+        command.env("XDG_CONFIG_HOME", &user_config);
+        command.env("APPDATA", &user_config);
+        command.env_remove("ALIAS_CONFIG");
+        command.env_remove("ALIAS_DISABLE");
         command
     }
 }
@@ -186,6 +204,18 @@ fn write_nesting_printer(path: &Path) -> PathBuf {
     write_script(path, "printf '%s\\n' \"${ALIAS_DEPTH}\"\n")
 }
 
+#[cfg(windows)]
+fn write_context_printer(path: &Path) -> PathBuf {
+    let target = path.with_extension("cmd");
+    fs::write(&target, "@echo off\r\necho %ALIAS_TEST_CONTEXT%\r\n").expect("a target program");
+    target
+}
+
+#[cfg(unix)]
+fn write_context_printer(path: &Path) -> PathBuf {
+    write_script(path, "printf '%s\\n' \"${ALIAS_TEST_CONTEXT}\"\n")
+}
+
 #[cfg(unix)]
 fn write_script(path: &Path, body: &str) -> PathBuf {
     use std::os::unix::fs::PermissionsExt;
@@ -218,6 +248,52 @@ fn a_quoted_part_of_an_alias_arrives_as_a_single_argument() {
     );
 }
 
+#[test]
+fn every_element_of_an_args_array_arrives_as_one_argument() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[alias]\nci = { args = [\"commit\", \"-m\", \"it's done\"] }",
+    );
+
+    let output = wrapper.run(&["ci", "--amend"]);
+
+    assert_eq!(
+        vec!["commit", "-m", "it's done", "--amend"],
+        stdout_lines(&output)
+    );
+}
+
+#[test]
+fn positional_parameters_are_filled_in_and_the_rest_is_appended() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias]\ncp = \"container cp $1 web:/tmp/$2\"");
+
+    let output = wrapper.run(&["cp", "a.txt", "b.txt", "--archive"]);
+
+    assert_eq!(
+        vec!["container", "cp", "a.txt", "web:/tmp/b.txt", "--archive"],
+        stdout_lines(&output)
+    );
+}
+
+// Nothing is run with a hole in it: the target never sees 'HEAD~'.
+#[test]
+fn a_missing_positional_argument_is_reported_instead_of_run() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias]\nrb = \"rebase -i HEAD~$1\"");
+
+    let output = wrapper.run(&["rb"]);
+
+    assert_eq!(Some(1), output.status.code(), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("argument $1 is missing"),
+        "unexpected error: {}",
+        stderr(&output)
+    );
+    assert!(
+        stdout_lines(&output).is_empty(),
+        "the target ran after all: {}",
+        stdout(&output)
+    );
+}
+
 #[test]
 fn a_nested_group_resolves_to_the_alias_at_its_deepest_level() {
     let wrapper = Wrapper::fronting_argv_printer("[alias.docker.container]\nls = \"container ls\"");
@@ -246,6 +322,171 @@ fn a_group_without_a_matching_member_is_forwarded_untouched() {
     assert_eq!(vec!["docker", "images"], stdout_lines(&output));
 }
 
+#[test]
+fn an_alias_built_on_another_alias_runs_the_target_once() {
+    let wrapper = Wrapper::fronting(
+        "[alias]\nsync = \"pull --rebase\"\nup = \"sync --autostash\"",
+        write_nesting_printer,
+    );
+
+    let output = wrapper.run(&["up"]);
+
+    assert_eq!(vec!["1"], stdout_lines(&output), "{}", stderr(&output));
+}
+
+#[test]
+fn an_alias_cycle_is_reported_before_anything_runs() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias]\na = \"b\"\nb = \"a\"");
+
+    let output = wrapper.run(&["a"]);
+
+    assert_eq!(Some(1), output.status.code(), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("alias cycle: a -> b -> a"),
+        "unexpected error: {}",
+        stderr(&output)
+    );
+    assert!(stdout_lines(&output).is_empty(), "the target ran after all");
+}
+
+#[test]
+fn a_project_file_is_used_once_trusted_and_again_only_after_a_change_is_trusted() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias]\nco = \"checkout\"");
+    let project = tempfile::tempdir().expect("a project directory");
+    let file = project.path().join(".alias").join("frontend.toml");
+    let nested = project.path().join("src");
+    fs::create_dir_all(file.parent().unwrap()).expect("the .alias directory");
+    fs::create_dir_all(&nested).expect("a directory below the project root");
+    fs::write(&file, "[alias]\nup = \"compose up\"\n").expect("a project file");
+
+    let untrusted = wrapper.run_in(&nested, &["up"]);
+    assert_eq!(vec!["up"], stdout_lines(&untrusted));
+    assert!(stderr(&untrusted).contains("is not trusted"));
+
+    let trusting = wrapper.run_in(&nested, &["--alias-trust"]);
+    assert!(trusting.status.success(), "{}", stderr(&trusting));
+
+    let trusted = wrapper.run_in(&nested, &["up", "-d"]);
+    assert_eq!(vec!["compose", "up", "-d"], stdout_lines(&trusted));
+    assert_eq!(
+        vec!["checkout"],
+        stdout_lines(&wrapper.run_in(&nested, &["co"]))
+    );
+
+    fs::write(&file, "[alias]\nup = \"compose down\"\n").expect("a changed project file");
+    let changed = wrapper.run_in(&nested, &["up"]);
+    assert_eq!(vec!["up"], stdout_lines(&changed));
+    assert!(stderr(&changed).contains("has changed since it was trusted"));
+}
+
+#[test]
+fn trusting_without_a_project_file_is_reported() {
+    let wrapper = Wrapper::fronting_argv_printer("");
+    let elsewhere = tempfile::tempdir().expect("a directory without a project file");
+
+    let output = wrapper.run_in(elsewhere.path(), &["--alias-trust"]);
+
+    assert_eq!(Some(1), output.status.code());
+    assert!(stderr(&output).contains(".alias/frontend.toml"));
+}
+
+#[test]
+fn the_user_config_is_layered_under_the_one_beside_the_wrapper() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias]\nco = \"checkout\"");
+    let user_config = wrapper.directory.path().join("user-config").join("alias");
+    fs::create_dir_all(&user_config).expect("a user config directory");
+    fs::write(
+        user_config.join("frontend.toml"),
+        "[alias]\nco = \"checkout user\"\nst = \"status\"\n",
+    )
+    .expect("a user config");
+
+    assert_eq!(vec!["checkout"], stdout_lines(&wrapper.run(&["co"])));
+    assert_eq!(vec!["status"], stdout_lines(&wrapper.run(&["st"])));
+}
+
+#[test]
+fn the_file_alias_config_names_has_the_last_word() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias]\nco = \"checkout\"");
+    let explicit = wrapper.directory.path().join("explicit.toml");
+    fs::write(&explicit, "[alias]\nco = \"checkout explicit\"\n").expect("a config file");
+
+    let output = wrapper.run_with("ALIAS_CONFIG", &explicit.display().to_string(), &["co"]);
+
+    assert_eq!(vec!["checkout", "explicit"], stdout_lines(&output));
+}
+
+#[test]
+fn a_file_alias_config_names_that_is_not_there_is_reported() {
+    let wrapper = Wrapper::fronting_argv_printer("");
+    let missing = wrapper.directory.path().join("missing.toml");
+
+    let output = wrapper.run_with("ALIAS_CONFIG", &missing.display().to_string(), &["co"]);
+
+    assert_eq!(Some(1), output.status.code());
+    assert!(stderr(&output).contains("missing.toml"));
+}
+
+#[test]
+fn the_check_reports_every_problem_with_its_position_and_fails() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias]\nci = 'commit -m \"wip'\nn = 3");
+
+    let output = wrapper.run(&["--alias-check"]);
+
+    assert_eq!(Some(1), output.status.code());
+    let printed = stdout(&output);
+    assert!(
+        printed.contains("config.toml:4:1: alias 'ci': unclosed quote"),
+        "missing from:\n{}",
+        printed
+    );
+    assert!(
+        printed.contains(
+            "config.toml:5:1: alias 'n' has to be a string, an array of steps or a table"
+        ),
+        "missing from:\n{}",
+        printed
+    );
+}
+
+#[test]
+fn the_check_reaches_a_config_that_does_not_even_parse() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias]\nco = \"checkout");
+
+    let output = wrapper.run(&["--alias-check"]);
+
+    assert_eq!(Some(1), output.status.code());
+    assert!(
+        stdout(&output).contains("config.toml:4:"),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn a_misspelled_setting_is_warned_about_and_the_call_still_runs() {
+    let wrapper = Wrapper::fronting_argv_printer("run_as_shel = true\n[alias]\nco = \"checkout\"");
+
+    let output = wrapper.run(&["co"]);
+
+    assert_eq!(vec!["checkout"], stdout_lines(&output));
+    assert!(
+        stderr(&output).contains("config.toml:3:1: unknown key 'run_as_shel'"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn a_clean_config_passes_the_check() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias]\nco = \"checkout\"");
+
+    let output = wrapper.run(&["--alias-check"]);
+
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(stdout(&output).contains("no problems"));
+}
+
 #[test]
 fn the_exit_code_of_the_target_is_the_exit_code_of_the_wrapper() {
     let wrapper = Wrapper::fronting("[alias]\nco = \"checkout\"", write_failing_target);
@@ -269,6 +510,252 @@ fn a_target_that_cannot_be_started_is_reported_as_127() {
     );
 }
 
+#[test]
+fn a_group_called_on_its_own_runs_its_default() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[alias.container]\n_default = \"container ls\"\nrm = \"container rm\"",
+    );
+
+    let output = wrapper.run(&["container", "-a"]);
+
+    assert_eq!(vec!["container", "ls", "-a"], stdout_lines(&output));
+}
+
+#[test]
+fn the_default_of_a_group_is_listed_apart_from_its_members() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[alias.container]\n_default = \"container ls\"\nrm = \"container rm\"",
+    );
+
+    let output = wrapper.run(&["--aliases"]);
+
+    let listing = stdout(&output);
+    assert!(
+        listing.contains("container:\n  (default) = container ls\n  rm = container rm\n"),
+        "default missing from:\n{}",
+        listing
+    );
+}
+
+#[test]
+fn the_prefix_of_a_group_is_put_in_front_of_the_alias() {
+    let wrapper =
+        Wrapper::fronting_argv_printer("[alias.image]\n_prefix = \"image\"\nbuild = \"build -t\"");
+
+    let output = wrapper.run(&["image", "build", "app"]);
+
+    assert_eq!(vec!["image", "build", "-t", "app"], stdout_lines(&output));
+}
+
+#[test]
+fn a_subcommand_no_alias_names_goes_to_the_wildcard_of_the_group() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias.k]\n\"*\" = \"--context prod $0\"");
+
+    let output = wrapper.run(&["k", "get", "pods"]);
+
+    assert_eq!(
+        vec!["--context", "prod", "get", "pods"],
+        stdout_lines(&output)
+    );
+}
+
+#[test]
+fn a_pattern_alias_gets_what_its_pattern_captured() {
+    let wrapper =
+        Wrapper::fronting_argv_printer("[pattern_alias]\n'pr(\\d+)' = \"pr checkout $1\"");
+
+    let output = wrapper.run(&["pr123"]);
+
+    assert_eq!(vec!["pr", "checkout", "123"], stdout_lines(&output));
+}
+
+#[test]
+fn pattern_aliases_are_listed_in_a_section_of_their_own() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[alias]\nco = \"checkout\"\n[pattern_alias]\n'pr(\\d+)' = \"pr checkout $1\"",
+    );
+
+    let output = wrapper.run(&["--aliases"]);
+
+    let listing = stdout(&output);
+    assert!(
+        listing.contains("co = checkout\n\n[pattern_alias]\n  pr(\\d+) = pr checkout $1\n"),
+        "patterns missing from:\n{}",
+        listing
+    );
+}
+
+#[test]
+fn an_ambiguous_prefix_fails_with_the_candidates_instead_of_reaching_the_target() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "matching = \"prefix\"\n[alias]\ncommit-all = \"commit -a\"\nconfig-list = \"config -l\"",
+    );
+
+    let output = wrapper.run(&["co"]);
+
+    assert_eq!(Some(1), output.status.code());
+    assert!(stdout(&output).is_empty(), "{}", stdout(&output));
+    assert!(
+        stderr(&output).contains("commit-all, config-list"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn a_near_miss_below_a_group_is_pointed_out_and_still_passed_on() {
+    let wrapper =
+        Wrapper::fronting_argv_printer("[alias.container]\nlog = \"logs -f\"\nclean = \"prune\"");
+
+    let output = wrapper.run(&["container", "lgo", "web"]);
+
+    assert_eq!(vec!["container", "lgo", "web"], stdout_lines(&output));
+    assert!(
+        stderr(&output).contains("'lgo' is not a member of 'container'; did you mean log?"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn a_miss_a_swap_and_a_letter_away_from_a_short_member_is_pointed_out() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias.container]\nlogs = \"logs -f\"");
+
+    let output = wrapper.run(&["container", "lgo", "tail"]);
+
+    assert_eq!(vec!["container", "lgo", "tail"], stdout_lines(&output));
+    assert!(
+        stderr(&output).contains("'lgo' is not a member of 'container'; did you mean logs?"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn a_strict_group_does_not_pass_a_miss_on() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "strict_groups = true\n[alias.container]\nlog = \"logs -f\"",
+    );
+
+    let output = wrapper.run(&["container", "lgo"]);
+
+    assert_eq!(Some(1), output.status.code());
+    assert!(stdout(&output).is_empty(), "{}", stdout(&output));
+}
+
+#[test]
+fn an_alias_after_a_declared_global_option_is_resolved() {
+    let wrapper =
+        Wrapper::fronting_argv_printer("[global_options]\n\"-C\" = 1\n[alias]\nco = \"checkout\"");
+
+    let output = wrapper.run(&["-C", "../other", "co", "main"]);
+
+    assert_eq!(
+        vec!["-C", "../other", "checkout", "main"],
+        stdout_lines(&output)
+    );
+}
+
+#[test]
+fn a_shell_alias_after_a_declared_global_option_is_passed_on_as_it_is() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[global_options]\n\"-C\" = 1\n[alias]\nst = \"!echo shell\"",
+    );
+
+    let output = wrapper.run(&["-C", "../other", "st"]);
+
+    assert_eq!(Some(0), output.status.code(), "{}", stderr(&output));
+    assert_eq!(vec!["-C", "../other", "st"], stdout_lines(&output));
+}
+
+#[test]
+fn flag_aliases_are_rewritten_in_expansions_and_in_what_is_passed_on() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[flag_alias]\n-A = \"--all-namespaces\"\n[alias]\npods = \"get pods -A\"",
+    );
+
+    let expanded = wrapper.run(&["pods", "-o", "wide"]);
+    let passed_on = wrapper.run(&["get", "svc", "-A", "--", "-A"]);
+
+    assert_eq!(
+        vec!["get", "pods", "--all-namespaces", "-o", "wide"],
+        stdout_lines(&expanded)
+    );
+    assert_eq!(
+        vec!["get", "svc", "--all-namespaces", "--", "-A"],
+        stdout_lines(&passed_on)
+    );
+}
+
+#[test]
+fn defaults_are_added_to_what_is_passed_on_but_not_to_expansions() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[defaults]\nlog = \"--no-pager\"\n[alias]\nlast = \"log -1\"",
+    );
+
+    let passed_on = wrapper.run(&["log", "-3"]);
+    let expanded = wrapper.run(&["last"]);
+
+    assert_eq!(vec!["log", "--no-pager", "-3"], stdout_lines(&passed_on));
+    assert_eq!(vec!["log", "-1"], stdout_lines(&expanded));
+}
+
+#[test]
+fn an_escaped_call_reaches_the_subcommand_an_alias_shadows() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[defaults]\nlog = \"--no-pager\"\n[flag_alias]\n-A = \"--all\"\n[alias]\nlog = \"log --oneline\"",
+    );
+
+    let escaped = wrapper.run(&["\\log", "-A"]);
+    let flagged = wrapper.run(&["--alias-raw", "log", "-A"]);
+
+    assert_eq!(vec!["log", "-A"], stdout_lines(&escaped));
+    assert_eq!(vec!["log", "-A"], stdout_lines(&flagged));
+}
+
+#[test]
+fn a_disabled_wrapper_passes_everything_on_as_it_is() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias]\nco = \"checkout\"");
+
+    let aliased = wrapper.run_with("ALIAS_DISABLE", "1", &["co", "main"]);
+    let listed = wrapper.run_with("ALIAS_DISABLE", "1", &["--aliases"]);
+
+    assert_eq!(vec!["co", "main"], stdout_lines(&aliased));
+    assert_eq!(vec!["--aliases"], stdout_lines(&listed));
+}
+
+// The escape is the way out when the config is what is broken: the target
+// another layer names is still found, and nothing is said on the way, neither
+// about the file that does not parse nor about an unknown key in the other.
+#[test]
+fn a_raw_call_gets_past_a_config_that_does_not_parse() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias\nco = \"checkout\"");
+    let user_config = wrapper.directory.path().join("user-config").join("alias");
+    fs::create_dir_all(&user_config).expect("a user config directory");
+    fs::write(
+        user_config.join("frontend.toml"),
+        format!(
+            "executable={}\ncolour = true\n",
+            as_toml_string(&wrapper.target.display().to_string())
+        ),
+    )
+    .expect("a user config naming the target");
+
+    let escaped = wrapper.run(&["\\co", "main"]);
+    let disabled = wrapper.run_with("ALIAS_DISABLE", "1", &["co"]);
+    let aliased = wrapper.run(&["co"]);
+
+    assert_eq!(vec!["co", "main"], stdout_lines(&escaped));
+    assert_eq!("", stderr(&escaped));
+    assert_eq!(vec!["co"], stdout_lines(&disabled));
+    assert_eq!("", stderr(&disabled));
+    assert_eq!(
+        Some(1),
+        aliased.status.code(),
+        "a call that is not raw still stops at the broken file"
+    );
+}
+
 #[test]
 fn configured_aliases_are_listed() {
     let wrapper = Wrapper::fronting_argv_printer(
@@ -295,6 +782,47 @@ fn configured_aliases_are_listed() {
     );
 }
 
+#[test]
+fn the_details_of_an_alias_are_listed_under_it() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[alias.docker]\n_description = \"Container shortcuts\"\n\
+         ps = { args = \"container ls\", description = \"Running containers\", tags = [\"ops\"] }",
+    );
+
+    let listing = stdout(&wrapper.run(&["--aliases"]));
+
+    for expected in [
+        "docker:",
+        "  # Container shortcuts",
+        "  ps = container ls",
+        "    # Running containers",
+        "    # tags: ops",
+    ] {
+        assert!(
+            listing.lines().any(|line| line == expected),
+            "{:?} missing from:\n{}",
+            expected,
+            listing
+        );
+    }
+}
+
+// The target knows nothing about the wrapper's tags, so its own answer to
+// --aliases is left out of a filtered listing.
+#[test]
+fn a_listing_by_tag_shows_only_the_aliases_carrying_it() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[alias]\npsql = { args = \"exec db psql\", tags = [\"db\"] }\nps = \"container ls\"",
+    );
+
+    let output = wrapper.run(&["--aliases", "--tag", "db"]);
+
+    assert_eq!(
+        vec!["psql = exec db psql", "  # tags: db"],
+        stdout_lines(&output)
+    );
+}
+
 #[test]
 fn the_wrapper_reports_its_own_version_before_the_version_of_the_target() {
     let wrapper = Wrapper::fronting_argv_printer("[alias]\nco = \"checkout\"");
@@ -343,11 +871,21 @@ fn the_help_names_everything_the_wrapper_answers_to() {
 
     let printed = stdout(&wrapper.run(&["--help"]));
 
-    for answered in ["--aliases", "--version", "--help", "ALIAS_DRY_RUN"] {
+    for answered in [
+        "--aliases",
+        "--tag",
+        "--alias-check",
+        "--alias-trust",
+        "--alias-raw",
+        "--version",
+        "--help",
+        "ALIAS_CONFIG",
+        "ALIAS_DRY_RUN",
+        "ALIAS_DISABLE",
+    ] {
         assert!(
             printed.contains(answered),
-            "{} missing from:
-{}",
+            "{} missing from:\n{}",
             answered,
             printed
         );
@@ -537,13 +1075,18 @@ fn the_config_created_on_the_first_launch_is_read_back_on_the_second() {
 
     let path = std::env::join_paths([wrapper_directory.path(), target_directory.path()])
         .expect("a PATH out of two directories");
+    // The sample goes to the user config directory first, so that has to be
+    // one of the test's own.
+    let user_config = wrapper_directory.path().join("user-config");
 
     let run = || {
         let mut command = Command::new(&binary);
         command
             .arg("--version")
             .env("SHELL", "/bin/sh")
-            .env("PATH", &path);
+            .env("PATH", &path)
+            .env("XDG_CONFIG_HOME", &user_config)
+            .env("APPDATA", &user_config);
         execute(command)
     };
 
@@ -555,7 +1098,7 @@ fn the_config_created_on_the_first_launch_is_read_back_on_the_second() {
         stderr(&first)
     );
 
-    let created = fs::read_to_string(wrapper_directory.path().join("config.toml"))
+    let created = fs::read_to_string(user_config.join("alias").join("frontend.toml"))
         .expect("the first launch creates a config");
     assert!(
         created.contains(&name),
@@ -599,6 +1142,346 @@ fn a_depth_that_is_not_a_number_counts_as_no_nesting() {
     assert_eq!(vec!["1"], stdout_lines(&output));
 }
 
+#[test]
+fn the_target_runs_with_the_environment_of_the_alias() {
+    let wrapper = Wrapper::fronting(
+        "[env]\nALIAS_TEST_CONTEXT = \"default\"\n[alias.prod]\n_env = { ALIAS_TEST_CONTEXT = \"prod\" }\npods = \"get pods\"",
+        write_context_printer,
+    );
+
+    assert_eq!(vec!["prod"], stdout_lines(&wrapper.run(&["prod", "pods"])));
+    assert_eq!(
+        vec!["default"],
+        stdout_lines(&wrapper.run(&["get", "pods"]))
+    );
+}
+
+#[test]
+fn a_dry_run_shows_the_environment_the_config_sets() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[alias]\nco = { args = \"checkout\", env = { GIT_PAGER = \"cat\", LESS = { unset = true } } }",
+    );
+
+    let printed = stdout(&wrapper.run_with("ALIAS_DRY_RUN", "1", &["co"]));
+
+    assert!(
+        printed.contains("env:\n  GIT_PAGER=cat\n  LESS is unset"),
+        "missing from:\n{}",
+        printed
+    );
+}
+
+// The command could ask for a passphrase, or change something: a dry run
+// only says what it would run.
+#[cfg(unix)]
+#[test]
+fn a_dry_run_names_the_command_for_a_variable_without_running_it() {
+    let directory = tempfile::tempdir().expect("a temporary directory");
+    let marker = directory.path().join("ran");
+    let wrapper = Wrapper::fronting_argv_printer(&format!(
+        "[alias]\ndeploy = {{ args = \"deploy\", env_from_command = {{ TOKEN = {} }} }}",
+        as_toml_string(&format!("touch {}", marker.display()))
+    ));
+
+    let printed = stdout(&wrapper.run_with("ALIAS_DRY_RUN", "1", &["deploy"]));
+
+    assert!(
+        printed.contains(&format!(
+            "TOKEN is set from command touch {}, not run",
+            marker.display()
+        )),
+        "missing from:\n{}",
+        printed
+    );
+    assert!(!marker.exists(), "the command ran during a dry run");
+}
+
+#[cfg(unix)]
+#[test]
+fn a_failing_command_for_a_variable_stops_the_call_before_the_target_starts() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[alias]\ndeploy = { args = \"deploy\", env_from_command = { TOKEN = \"false\" } }",
+    );
+
+    let output = wrapper.run(&["deploy"]);
+
+    assert_eq!(Some(1), output.status.code());
+    assert!(
+        stdout(&output).is_empty(),
+        "the target ran: {}",
+        stdout(&output)
+    );
+    assert!(
+        stderr(&output).contains("the command for TOKEN (false) failed with exit code 1"),
+        "unexpected error: {}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn the_steps_of_an_alias_run_one_after_another() {
+    let wrapper =
+        Wrapper::fronting_argv_printer("[alias]\nsync = [\"fetch --prune\", \"rebase main\"]");
+
+    let output = wrapper.run(&["sync", "-i"]);
+
+    assert_eq!(Some(0), output.status.code(), "{}", stderr(&output));
+    assert_eq!(
+        vec!["fetch", "--prune", "rebase", "main", "-i"],
+        stdout_lines(&output)
+    );
+}
+
+// Prints its arguments on one line, and fails with 3 when the first one says
+// so.
+#[cfg(unix)]
+fn write_step_runner(path: &Path) -> PathBuf {
+    write_script(
+        path,
+        "printf '%s\\n' \"$*\"\n[ \"$1\" != fail ] || exit 3\n",
+    )
+}
+
+#[cfg(unix)]
+#[test]
+fn a_failing_step_stops_the_rest_and_its_code_is_the_exit_code() {
+    let wrapper = Wrapper::fronting(
+        "[alias]\nsync = [\"fetch\", \"fail rebase\", \"push\"]",
+        write_step_runner,
+    );
+
+    let output = wrapper.run(&["sync"]);
+
+    assert_eq!(Some(3), output.status.code(), "{}", stderr(&output));
+    assert_eq!(vec!["fetch", "fail rebase"], stdout_lines(&output));
+}
+
+#[cfg(unix)]
+#[test]
+fn with_continue_on_error_every_step_runs_and_the_first_failure_is_the_exit_code() {
+    let wrapper = Wrapper::fronting(
+        "[alias]\ntidy = { steps = [\"fail gc\", \"prune\"], continue_on_error = true }",
+        write_step_runner,
+    );
+
+    let output = wrapper.run(&["tidy"]);
+
+    assert_eq!(Some(3), output.status.code(), "{}", stderr(&output));
+    assert_eq!(vec!["fail gc", "prune"], stdout_lines(&output));
+}
+
+#[test]
+fn a_dry_run_shows_every_step() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[alias]\nsync = { steps = [\"fetch\", \"push\"], env = { GIT_PAGER = \"cat\" } }",
+    );
+
+    let printed = stdout(&wrapper.run_with("ALIAS_DRY_RUN", "1", &["sync"]));
+
+    assert!(
+        printed.contains("steps: 2, stopping at the first that fails\nstep 1:\n"),
+        "missing from:\n{}",
+        printed
+    );
+    assert!(
+        printed.contains("argv:\n  [1] fetch\nstep 2:\n"),
+        "missing from:\n{}",
+        printed
+    );
+    assert!(
+        printed.ends_with("argv:\n  [1] push\nenv:\n  GIT_PAGER=cat\n"),
+        "missing from:\n{}",
+        printed
+    );
+}
+
+// No shell anywhere: the pipe is set up by the wrapper itself.
+#[cfg(unix)]
+#[test]
+fn a_pipe_feeds_the_output_of_the_target_to_another_program() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[alias]\npods = { pipe = [\"get pods\", [\"!tr\", \"a-z\", \"A-Z\"]] }",
+    );
+
+    let output = wrapper.run_without("SHELL", &["pods", "-a"]);
+
+    assert_eq!(Some(0), output.status.code(), "{}", stderr(&output));
+    assert_eq!(vec!["GET", "PODS", "-A"], stdout_lines(&output));
+}
+
+#[cfg(unix)]
+#[test]
+fn a_pipe_fails_with_the_code_of_its_last_stage_that_failed() {
+    let wrapper = Wrapper::fronting(
+        "[alias]\nhead = { pipe = [\"fail get\", \"!cat\"] }\ntail = { pipe = [\"get\", \"!false\"] }",
+        write_step_runner,
+    );
+
+    let output = wrapper.run(&["head"]);
+    assert_eq!(Some(3), output.status.code(), "{}", stderr(&output));
+    assert_eq!(vec!["fail get"], stdout_lines(&output));
+
+    let output = wrapper.run(&["tail"]);
+    assert_eq!(Some(1), output.status.code(), "{}", stderr(&output));
+}
+
+#[cfg(unix)]
+#[test]
+fn a_stage_that_cannot_be_started_is_reported_as_127() {
+    let wrapper = Wrapper::fronting_argv_printer(
+        "[alias]\npods = { pipe = [\"get pods\", \"!alias-test-no-such-program\"] }",
+    );
+
+    let output = wrapper.run(&["pods"]);
+
+    assert_eq!(Some(127), output.status.code());
+    assert!(
+        stderr(&output).contains("alias-test-no-such-program"),
+        "unexpected error: {}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn a_dry_run_shows_every_stage_of_a_pipe() {
+    let wrapper =
+        Wrapper::fronting_argv_printer("[alias]\npods = { pipe = [\"get pods\", \"!sort -r\"] }");
+
+    let printed = stdout(&wrapper.run_with("ALIAS_DRY_RUN", "1", &["pods"]));
+
+    assert!(
+        printed
+            .contains("pipe: 2 stages, each one reading what the one before it writes\nstage 1:\n"),
+        "missing from:\n{}",
+        printed
+    );
+    assert!(
+        printed.ends_with("stage 2:\nexecutable: sort\nargv:\n  [1] -r\n"),
+        "missing from:\n{}",
+        printed
+    );
+}
+
+// Says so when told to stop, and gives up on its own after ten seconds, so
+// that a signal that never arrives fails the test rather than hanging it.
+#[cfg(unix)]
+fn write_stoppable_target(path: &Path) -> PathBuf {
+    write_script(
+        path,
+        "trap 'echo stopped; exit 5' TERM\necho started\n\
+         i=0\nwhile [ $i -lt 100 ]; do sleep 0.1; i=$((i + 1)); done\n",
+    )
+}
+
+// A 'kill' knows only the wrapper's pid: the stages hear of it through the
+// wrapper, which then exits with what they made of it. Both stages have to
+// stop for the wrapper to exit at all; only the second one's output is seen.
+#[cfg(unix)]
+#[test]
+fn a_signal_sent_to_the_wrapper_reaches_the_stages_of_a_pipe() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let wrapper = Wrapper::fronting(
+        "[alias]\nwatch = { pipe = [\"watch\", \"watch\"] }",
+        write_stoppable_target,
+    );
+    let mut child = {
+        let _guard = EXECUTABLES.read().unwrap_or_else(PoisonError::into_inner);
+        let mut command = wrapper.command(&["watch"]);
+        command.stdout(Stdio::piped());
+        command.spawn().expect("the wrapper starts")
+    };
+    let mut lines = BufReader::new(child.stdout.take().expect("a pipe")).lines();
+    assert_eq!(
+        Some("started".to_string()),
+        lines.next().and_then(Result::ok)
+    );
+
+    let killed = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .expect("kill runs");
+    assert!(killed.success());
+
+    assert_eq!(
+        Some("stopped".to_string()),
+        lines.next().and_then(Result::ok)
+    );
+    assert_eq!(Some(5), child.wait().expect("the wrapper exits").code());
+}
+
+// The same for the steps of an alias: the wrapper waits for every one but the
+// last, and a 'kill' has to reach the step it is waiting for.
+#[cfg(unix)]
+#[test]
+fn a_signal_sent_to_the_wrapper_reaches_the_step_it_waits_for() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let wrapper = Wrapper::fronting(
+        "[alias]\nwatch = [\"watch\", \"watch\"]",
+        write_stoppable_target,
+    );
+    let mut child = {
+        let _guard = EXECUTABLES.read().unwrap_or_else(PoisonError::into_inner);
+        let mut command = wrapper.command(&["watch"]);
+        command.stdout(Stdio::piped());
+        command.spawn().expect("the wrapper starts")
+    };
+    let mut lines = BufReader::new(child.stdout.take().expect("a pipe")).lines();
+    assert_eq!(
+        Some("started".to_string()),
+        lines.next().and_then(Result::ok)
+    );
+
+    let killed = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .expect("kill runs");
+    assert!(killed.success());
+
+    assert_eq!(
+        Some("stopped".to_string()),
+        lines.next().and_then(Result::ok)
+    );
+    assert_eq!(Some(5), child.wait().expect("the wrapper exits").code());
+}
+
+#[cfg(unix)]
+fn write_directory_printer(path: &Path) -> PathBuf {
+    write_script(path, "pwd -P\n")
+}
+
+// Started two levels below the marker, the target still runs at the root.
+#[cfg(unix)]
+#[test]
+fn an_alias_with_a_root_marker_runs_the_target_at_the_root_of_the_project() {
+    let wrapper = Wrapper::fronting(
+        "[alias]\nup = { args = \"up\", cwd = { root_marker = \"compose.yaml\" } }",
+        write_directory_printer,
+    );
+    let project = tempfile::tempdir().expect("a temporary directory");
+    let nested = project.path().join("services").join("api");
+    fs::create_dir_all(&nested).expect("a nested directory");
+    fs::write(project.path().join("compose.yaml"), "").expect("a marker");
+    let root = fs::canonicalize(project.path()).expect("a canonical path");
+
+    let output = wrapper.run_in(&nested, &["up"]);
+
+    assert_eq!(Some(0), output.status.code(), "{}", stderr(&output));
+    assert_eq!(vec![root.display().to_string()], stdout_lines(&output));
+}
+
+#[test]
+fn a_dry_run_shows_the_working_directory() {
+    let wrapper = Wrapper::fronting_argv_printer("[alias]\nup = { args = \"up\", cwd = \".\" }");
+
+    let printed = stdout(&wrapper.run_with("ALIAS_DRY_RUN", "1", &["up"]));
+
+    assert!(printed.contains("cwd: "), "missing from:\n{}", printed);
+}
+
 // The backstop: reaching the limit stops the chain instead of leaving it to run
 // until the machine gives up.
 #[test]